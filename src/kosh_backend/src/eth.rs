@@ -1,13 +1,19 @@
-use candid::Nat;
+use candid::{CandidType, Deserialize, Nat, Principal};
 use ethabi::ethereum_types::{H160, U256};
 use ethabi::{Address, Function, Param, ParamType, Token};
 use ethers_core::types::{Bytes, Eip1559TransactionRequest, U64};
 use hex;
-use ic_cdk::api::call::call_with_payment128;
+use ic_cdk::api::call::{call_with_payment128, CallResult, RejectionCode};
 use ic_cdk::update;
 use k256::PublicKey;
 use sha2::Digest;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::task::{Poll, Waker};
+use std::time::Duration;
 
 use crate::evm_indexer::ChainService;
 use crate::evm_rpc_bindings::GetTransactionCountResult;
@@ -18,6 +24,12 @@ use crate::evm_rpc_bindings::{
     EthSepoliaService, GetTransactionCountArgs, MultiSendRawTransactionResult, RpcApi,
     SendRawTransactionStatus,
 };
+use crate::evm_rpc_bindings::{
+    FeeHistory, FeeHistoryArgs, FeeHistoryResult, MultiFeeHistoryResult,
+};
+use crate::evm_rpc_bindings::{GetTransactionReceiptResult, MultiGetTransactionReceiptResult};
+use crate::evm_rpc_bindings::{ProviderError, RequestResult, RpcError, RpcService};
+use crate::require_controller;
 use ic_cdk::api::management_canister::ecdsa::sign_with_ecdsa;
 use ic_cdk::api::management_canister::ecdsa::EcdsaPublicKeyResponse;
 use ic_cdk::api::management_canister::ecdsa::SignWithEcdsaResponse;
@@ -31,741 +43,4535 @@ use std::sync::RwLock;
 
 static TX_HASH: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
 
-const CONTRACT_ADDRESS_HEX: &str = "0x99a79158A40E4BEF8Beb3AcFAE893e62C45034E8";
-const EIP1559_TX_ID: u8 = 2;
+// Upper bound on named vaults so get_vault_balances stays within a single call's cycle budget
+const MAX_VAULTS: usize = 8;
+// Label reported for the canister's default (empty derivation path) address
+const CANISTER_VAULT_LABEL: &str = "canister";
+// Per-caller addresses reported by get_vault_balances, most recently active first
+const MAX_CALLER_VAULTS: usize = 8;
+// Prefix of a per-caller vault's label, followed by the caller's principal
+const CALLER_VAULT_PREFIX: &str = "caller:";
 
-// Helper function to get RPC configuration based on destination chain
-fn get_rpc_config(dest_chain: &str) -> (u64, String) {
-    match dest_chain {
-        "17000" => (
-            17000,
-            "https://ethereum-holesky-rpc.publicnode.com".to_string(),
-        ),
-        "8453" => (8453, "https://base.drpc.org".to_string()),
-        _ => (
-            17000,
-            "https://ethereum-holesky-rpc.publicnode.com".to_string(),
-        ), // Default to Holesky
-    }
+thread_local! {
+    static VAULT_LABELS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
 }
 
-impl ChainService {
-    async fn pubkey_and_signature(
-        &self,
-        tx_hash: Vec<u8>,
-    ) -> Result<(Vec<u8>, SignWithEcdsaResponse), String> {
-        let public_key_response = get_ecdsa_public_key().await?; // now a Result
+pub fn vault_labels_snapshot() -> Vec<String> {
+    VAULT_LABELS.with(|labels| labels.borrow().clone())
+}
 
-        let (signature_response,) = sign_with_ecdsa(SignWithEcdsaArgument {
-            message_hash: tx_hash,
-            key_id: key_id(),
-            ..Default::default()
-        })
-        .await
-        .map_err(|e| format!("Failed to generate signature {:?}", e))?;
+pub fn restore_vault_labels(labels: Vec<String>) {
+    VAULT_LABELS.with(|current| *current.borrow_mut() = labels);
+}
 
-        Ok((public_key_response.public_key, signature_response))
-    }
+// Gas limits for native sends: a plain transfer to an EOA, and a send to a contract whose
+// receive/fallback logic needs headroom
+const NATIVE_TRANSFER_GAS_LIMIT: u128 = 21_000;
+const CONTRACT_TRANSFER_GAS_LIMIT: u128 = 150_000;
 
-    async fn caller_pubkey_and_signature(
-        &self,
-        tx_hash: Vec<u8>,
-    ) -> Result<(Vec<u8>, SignWithEcdsaResponse), String> {
-        let public_key_response = get_caller_ecdsa_public_key().await?;
+// Largest JSON-RPC response accepted by default
+const DEFAULT_RESPONSE_BYTES: u64 = 2_000;
+// An `eth_getCode` response for the largest contract EIP-170 allows (24_576 bytes,
+// hex-encoded) plus the JSON-RPC envelope
+const CODE_RESPONSE_BYTES: u64 = 2 * 24_576 + 1_000;
 
-        let (signature_response,) = sign_with_ecdsa(SignWithEcdsaArgument {
-            message_hash: tx_hash,
-            derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
-            key_id: key_id(),
-            ..Default::default()
-        })
-        .await
-        .map_err(|e| format!("Failed to generate signature {:?}", e))?;
+thread_local! {
+    // Whether an address has code, keyed by (chain id, lower-case address)
+    static CODE_PRESENCE: RefCell<HashMap<(String, String), bool>> = RefCell::new(HashMap::new());
+}
 
-        Ok((public_key_response.public_key, signature_response))
-    }
+/// Signed bytes of a transaction the canister broadcast, kept for `rebroadcast`
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TxRecord {
+    pub raw_tx_hex: String,
+    pub dest_chain: String,
+    /// Nanoseconds since the epoch
+    pub sent_at: u64,
+}
 
-    /// Fetch transaction count (nonce) for your IC Ethereum address (from secp256k1 pubkey)
-    pub async fn fetch_tx_nonce(&self, dest_chain: &str) -> Result<Nat, String> {
-        let block_tag = BlockTag::Latest;
-        let (canister_address, _ecdsa_key) = get_network_config();
-        ic_cdk::println!("canister_address {}", canister_address);
-        let get_transaction_count_args = GetTransactionCountArgs {
-            address: canister_address.to_string(),
-            block: block_tag,
-        };
+// Most recent canister sends kept for rebroadcasting
+const MAX_SENT_TXS: usize = 100;
 
-        // Prepare cycles amount to pay for the call (adjust as necessary)
-        let cycles: u128 = 200_000_000_000u128;
+thread_local! {
+    // Keyed by tx hash
+    static SENT_TXS: RefCell<HashMap<String, TxRecord>> = RefCell::new(HashMap::new());
+}
 
-        // Get RPC configuration based on destination chain
-        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
-        ic_cdk::println!("Using chain_id: {} and rpc_url: {}", chain_id, rpc_url);
+pub fn sent_txs_snapshot() -> HashMap<String, TxRecord> {
+    SENT_TXS.with(|txs| txs.borrow().clone())
+}
 
-        // The principal (canister ID) of the EVM RPC canister
-        let evm_canister_id = self.evm_rpc.0;
+pub fn restore_sent_txs(txs: HashMap<String, TxRecord>) {
+    SENT_TXS.with(|current| *current.borrow_mut() = txs);
+}
 
-        // Make cross-canister call with cycles payment
-        let (transaction_result,) = call_with_payment128::<
-            (
-                RpcServices,
-                Option<crate::evm_rpc_bindings::RpcConfig>,
-                GetTransactionCountArgs,
-            ),
-            (MultiGetTransactionCountResult,),
-        >(
-            evm_canister_id,
-            "eth_getTransactionCount",
-            (
-                RpcServices::Custom {
-                    chainId: chain_id,
-                    services: vec![RpcApi {
-                        url: rpc_url,
-                        headers: None,
-                    }],
-                },
-                None,
-                get_transaction_count_args.clone(),
-            ),
-            cycles,
-        )
-        .await
-        .map_err(|e| format!("Failed to get transaction count: {:?}", e))?;
-
-        // Handle possible result variants
-        let transaction_count = match transaction_result {
-            MultiGetTransactionCountResult::Consistent(consistent_result) => {
-                match consistent_result {
-                    GetTransactionCountResult::Ok(count) => count,
-                    GetTransactionCountResult::Err(error) => {
-                        return Err(format!(
-                            "failed to get transaction count for {:?}, error: {:?}",
-                            get_transaction_count_args, error
-                        ));
-                    }
-                }
-            }
-            MultiGetTransactionCountResult::Inconsistent(inconsistent_results) => {
-                return Err(format!(
-                    "inconsistent results when retrieving transaction count for {:?}. Received results: {:?}",
-                    get_transaction_count_args,
-                    inconsistent_results
-                ));
+fn record_raw_tx(tx_hash: &str, raw_tx_hex: String, dest_chain: &str) {
+    SENT_TXS.with(|txs| {
+        let mut txs = txs.borrow_mut();
+        if txs.len() >= MAX_SENT_TXS {
+            let oldest = txs
+                .iter()
+                .min_by_key(|(_, record)| record.sent_at)
+                .map(|(hash, _)| hash.clone());
+            if let Some(oldest) = oldest {
+                txs.remove(&oldest);
             }
-        };
+        }
+        txs.insert(
+            tx_hash.to_string(),
+            TxRecord {
+                raw_tx_hex,
+                dest_chain: dest_chain.to_string(),
+                sent_at: ic_cdk::api::time(),
+            },
+        );
+    });
+}
 
-        Ok(transaction_count)
+/// Whether a send was refused only because the node already has the transaction
+pub fn is_already_known(error: &RpcError) -> bool {
+    match error {
+        RpcError::JsonRpcError(error) => {
+            let message = error.message.to_lowercase();
+            message.contains("already known") || message.contains("already imported")
+        }
+        _ => false,
     }
+}
 
-    /// Fetch transaction count (nonce) for caller's address using derivation path
-    pub async fn fetch_caller_tx_nonce(&self, dest_chain: &str) -> Result<Nat, String> {
-        let block_tag = BlockTag::Latest;
-        
-        // Get caller's Ethereum address
-        let caller_address = get_caller_ethereum_address().await?;
-        ic_cdk::println!("caller_address {}", caller_address);
-        
-        let get_transaction_count_args = GetTransactionCountArgs {
-            address: caller_address,
-            block: block_tag,
-        };
+thread_local! {
+    // (nonce, canonical chain id) of canister sends rejected as "nonce too high", i.e. sat
+    // behind a nonce gap; cleared by a successful clear_nonce at or past them
+    static STUCK_NONCES: RefCell<BTreeSet<(u64, String)>> = const { RefCell::new(BTreeSet::new()) };
+}
 
-        // Prepare cycles amount to pay for the call (adjust as necessary)
-        let cycles: u128 = 200_000_000_000u128;
+pub fn stuck_nonces() -> Vec<(u64, String)> {
+    STUCK_NONCES.with(|nonces| nonces.borrow().iter().cloned().collect())
+}
 
-        // Get RPC configuration based on destination chain
-        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
-        ic_cdk::println!("Using chain_id: {} and rpc_url: {}", chain_id, rpc_url);
+thread_local! {
+    // Refuse bridge payouts addressed to the canister's own address
+    static REJECT_SELF_RECIPIENT: RefCell<bool> = const { RefCell::new(true) };
+}
 
-        // The principal (canister ID) of the EVM RPC canister
-        let evm_canister_id = self.evm_rpc.0;
+pub fn reject_self_recipient() -> bool {
+    REJECT_SELF_RECIPIENT.with(|reject| *reject.borrow())
+}
 
-        // Make cross-canister call with cycles payment
-        let (transaction_result,) = call_with_payment128::<
-            (
-                RpcServices,
-                Option<crate::evm_rpc_bindings::RpcConfig>,
-                GetTransactionCountArgs,
-            ),
-            (MultiGetTransactionCountResult,),
-        >(
-            evm_canister_id,
-            "eth_getTransactionCount",
-            (
-                RpcServices::Custom {
-                    chainId: chain_id,
-                    services: vec![RpcApi {
-                        url: rpc_url,
-                        headers: None,
-                    }],
-                },
-                None,
-                get_transaction_count_args.clone(),
-            ),
-            cycles,
-        )
-        .await
-        .map_err(|e| format!("Failed to get transaction count: {:?}", e))?;
-
-        // Handle possible result variants
-        let transaction_count = match transaction_result {
-            MultiGetTransactionCountResult::Consistent(consistent_result) => {
-                match consistent_result {
-                    GetTransactionCountResult::Ok(count) => count,
-                    GetTransactionCountResult::Err(error) => {
-                        return Err(format!(
-                            "failed to get transaction count for {:?}, error: {:?}",
-                            get_transaction_count_args, error
-                        ));
-                    }
-                }
-            }
-            MultiGetTransactionCountResult::Inconsistent(inconsistent_results) => {
-                return Err(format!(
-                    "inconsistent results when retrieving transaction count for {:?}. Received results: {:?}",
-                    get_transaction_count_args,
-                    inconsistent_results
-                ));
-            }
-        };
+pub fn restore_reject_self_recipient(reject: bool) {
+    REJECT_SELF_RECIPIENT.with(|current| *current.borrow_mut() = reject);
+}
 
-        Ok(transaction_count)
-    }
+#[update]
+pub fn set_reject_self_recipient(reject: bool) -> Result<(), String> {
+    require_controller()?;
 
-    pub async fn send_eth_evm(
-        &self,
-        to: String,
-        amount: String,
-        dest_chain: String,
-    ) -> Result<String, String> {
-        use ethers_core::types::U256;
+    restore_reject_self_recipient(reject);
+    Ok(())
+}
 
-        ic_cdk::println!("dest_chain {}", dest_chain);
+/// Addresses bridge payouts are restricted to while `enabled`, for deployments that only
+/// ever pay known recipients
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct RecipientAllowlist {
+    pub enabled: bool,
+    /// EIP-55 checksummed addresses
+    pub addresses: BTreeSet<String>,
+}
 
-        // 4. Get nonce for from address
-        let nonce = self.fetch_tx_nonce(&dest_chain).await?;
-        ic_cdk::println!("Nonce for address: {}", nonce);
+thread_local! {
+    static RECIPIENT_ALLOWLIST: RefCell<RecipientAllowlist> = RefCell::new(RecipientAllowlist::default());
+}
 
-        ic_cdk::println!("AMOUNT {}", amount);
+pub fn recipient_allowlist() -> RecipientAllowlist {
+    RECIPIENT_ALLOWLIST.with(|allowlist| allowlist.borrow().clone())
+}
 
-        // 5. Estimate gas fees (implement your own or hardcode)
-        let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) =
-            estimate_transaction_fees().await;
+pub fn restore_recipient_allowlist(allowlist: RecipientAllowlist) {
+    RECIPIENT_ALLOWLIST.with(|current| *current.borrow_mut() = allowlist);
+}
 
-        // Get chain ID for the transaction
-        let (chain_id, _) = get_rpc_config(&dest_chain);
-        ic_cdk::println!("Building transaction for chain_id: {}", chain_id);
+/// Refuse a payout recipient missing from the allowlist; a disabled allowlist allows all
+pub fn check_recipient_allowed(recipient: &H160) -> Result<(), String> {
+    let allowed = RECIPIENT_ALLOWLIST.with(|allowlist| {
+        let allowlist = allowlist.borrow();
+        !allowlist.enabled
+            || allowlist
+                .addresses
+                .contains(&ethers_core::utils::to_checksum(recipient, None))
+    });
+    if !allowed {
+        return Err(format!(
+            "Recipient {} is not on the allowlist",
+            ethers_core::utils::to_checksum(recipient, None)
+        ));
+    }
+    Ok(())
+}
 
-        // 6. Build the EIP-1559 transaction request
-        let tx = Eip1559TransactionRequest {
-            from: None,
-            to: Some(H160::from_str(&to).map_err(|e| format!("Invalid address format: {}", e))?.into()),
-            nonce: Some(U256::from(nonce.0.to_u64().unwrap())),
-            gas: Some(U256::from(gas_limit)),
-            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
-            max_priority_fee_per_gas: Some(U256::from(max_priority_fee_per_gas)),
-            value: Some({
-                // Convert ETH decimal amount to wei for transaction
-                let eth_amount: f64 = amount.parse().map_err(|e| format!("Invalid ETH amount: {}", e))?;
-                let wei_amount = (eth_amount * 1e18) as u128;
-                U256::from(wei_amount)
-            }),
-            data: Default::default(),
-            access_list: vec![].into(),
-            chain_id: Some(U64::from(chain_id)),
-        };
+#[update]
+fn set_recipient_allowlist_enabled(enabled: bool) -> Result<(), String> {
+    require_controller()?;
 
-        // 7. RLP encode the unsigned transaction and prefix with EIP1559 tx id (0x02)
-        let mut unsigned_tx = tx.rlp().to_vec();
-        unsigned_tx.insert(0, EIP1559_TX_ID);
+    RECIPIENT_ALLOWLIST.with(|allowlist| allowlist.borrow_mut().enabled = enabled);
+    Ok(())
+}
 
-        // 8. Generate the transaction hash to sign (keccak256 of the unsigned_tx)
-        let tx_hash = ethers_core::utils::keccak256(&unsigned_tx);
+#[update]
+fn allow_recipient(address: String) -> Result<(), String> {
+    require_controller()?;
 
-        // 9. Sign the transaction hash with IC ECDSA key
-        let (public_key_bytes, signature) = self.pubkey_and_signature(tx_hash.to_vec()).await?;
+    let address = ethers_core::utils::to_checksum(&validate_eth_address(&address)?, None);
+    RECIPIENT_ALLOWLIST.with(|allowlist| allowlist.borrow_mut().addresses.insert(address));
+    Ok(())
+}
 
-        // 10. Recover y parity (v) from signature
-        let y_parity = y_parity(&tx_hash, &signature.signature, &public_key_bytes);
+#[update]
+fn disallow_recipient(address: String) -> Result<(), String> {
+    require_controller()?;
 
-        // 11. Create ethers Signature struct with r,s,v
-        let sig = ethers_core::types::Signature {
-            r: U256::from_big_endian(&signature.signature[0..32]),
-            s: U256::from_big_endian(&signature.signature[32..64]),
-            v: y_parity as u64,
-        };
+    let address = to_checksummed(&address)?;
+    RECIPIENT_ALLOWLIST.with(|allowlist| allowlist.borrow_mut().addresses.remove(&address));
+    Ok(())
+}
 
-        // 12. RLP encode the signed transaction and prefix with tx id
-        let mut signed_tx = tx.rlp_signed(&sig).to_vec();
-        signed_tx.insert(0, EIP1559_TX_ID);
+#[ic_cdk::query]
+fn get_recipient_allowlist() -> RecipientAllowlist {
+    recipient_allowlist()
+}
 
-        let raw_tx_hex = format!("0x{}", hex::encode(&signed_tx));
+thread_local! {
+    // Dry-run contract calls with eth_call before broadcasting them
+    static SIMULATE_BEFORE_SEND: RefCell<bool> = const { RefCell::new(true) };
+}
 
-        ic_cdk::println!("Raw signed transaction hex: {}", raw_tx_hex);
+pub fn simulate_before_send() -> bool {
+    SIMULATE_BEFORE_SEND.with(|simulate| *simulate.borrow())
+}
 
-        let cycles_to_pay: u128 = 600_000_000_000;
+pub fn restore_simulate_before_send(simulate: bool) {
+    SIMULATE_BEFORE_SEND.with(|current| *current.borrow_mut() = simulate);
+}
 
-        // Get RPC configuration for sending the transaction
-        let (chain_id, rpc_url) = get_rpc_config(&dest_chain);
-        ic_cdk::println!(
-            "Sending transaction to chain_id: {} with rpc_url: {}",
-            chain_id,
-            rpc_url
-        );
+#[update]
+pub fn set_simulate_before_send(simulate: bool) -> Result<(), String> {
+    require_controller()?;
 
-        // 13. Send the raw transaction using your evm_rpc canister's eth_sendRawTransaction
-        let (send_result,) = call_with_payment128::<
-            (
-                RpcServices,
-                Option<crate::evm_rpc_bindings::RpcConfig>,
-                String,
-            ),
-            (MultiSendRawTransactionResult,),
-        >(
-            self.evm_rpc.0, // the canister principal of your evm_rpc canister
-            "eth_sendRawTransaction",
-            (
-                RpcServices::Custom {
-                    chainId: chain_id,
-                    services: vec![RpcApi {
-                        url: rpc_url,
-                        headers: None,
-                    }],
-                },
-                None,
-                raw_tx_hex,
-            ),
-            cycles_to_pay, // send cycles for payment here
-        )
-        .await
-        .map_err(|e| format!("Failed to send raw transaction: {:?}", e))?;
+    restore_simulate_before_send(simulate);
+    Ok(())
+}
 
-        // 14. Parse result from send
-        match send_result {
-            MultiSendRawTransactionResult::Consistent(send_status) => match send_status {
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(opt_tx_hash)) => {
-                    if let Some(tx_hash) = opt_tx_hash {
-                        {
-                            let mut hash = TX_HASH.write().unwrap();
-                            *hash = Some(tx_hash.clone());
-                        }
-
-                        ic_cdk::println!(
-                            "✅ Transaction sent successfully, tx hash: {:?}",
-                            tx_hash
-                        );
-                        Ok(tx_hash)
-                    } else {
-                        Err("Error: transaction hash not found in the response".to_string())
-                    }
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow) => {
-                    Err("Error: nonce too low".to_string())
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooHigh) => {
-                    Err("Error: nonce too high".to_string())
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::InsufficientFunds) => {
-                    Err("Error: insufficient funds".to_string())
-                }
-                SendRawTransactionResult::Err(rpc_error) => {
-                    Err(format!("RPC error sending transaction: {:?}", rpc_error))
-                }
-            },
-            MultiSendRawTransactionResult::Inconsistent(_) => {
-                Err("Inconsistent send raw transaction results".to_string())
+thread_local! {
+    // ENS registry per canonical chain id; recipients given as names are only resolved on
+    // chains listed here
+    static ENS_REGISTRIES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+pub fn ens_registries() -> HashMap<String, String> {
+    ENS_REGISTRIES.with(|registries| registries.borrow().clone())
+}
+
+pub fn restore_ens_registries(registries: HashMap<String, String>) {
+    ENS_REGISTRIES.with(|current| *current.borrow_mut() = registries);
+}
+
+/// Resolve ENS-style recipient names on `dest_chain` through `registry`; `None` turns
+/// resolution off for the chain
+#[update]
+pub fn set_ens_registry(dest_chain: String, registry: Option<String>) -> Result<(), String> {
+    require_controller()?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    ENS_REGISTRIES.with(|registries| -> Result<(), String> {
+        let mut registries = registries.borrow_mut();
+        match registry {
+            Some(registry) => {
+                let registry = to_checksummed(&registry)
+                    .map_err(|e| format!("Invalid registry address: {}", e))?;
+                registries.insert(dest_chain, registry);
+            }
+            None => {
+                registries.remove(&dest_chain);
             }
         }
-    }
+        Ok(())
+    })
+}
 
-    pub async fn transfer_eth(
-        &self,
+#[ic_cdk::query]
+pub fn get_ens_registries() -> HashMap<String, String> {
+    ens_registries()
+}
+
+/// Whether `recipient` is a name to resolve rather than a hex address
+pub fn looks_like_ens_name(recipient: &str) -> bool {
+    !recipient.starts_with("0x") && recipient.contains('.')
+}
+
+/// EIP-137 namehash of `name`, labels lowercased
+pub fn ens_namehash(name: &str) -> Result<[u8; 32], String> {
+    let mut node = [0u8; 32];
+    for label in name.rsplit('.') {
+        if label.is_empty() {
+            return Err(format!("Invalid ENS name {}: empty label", name));
+        }
+        let label_hash = ethers_core::utils::keccak256(label.to_lowercase());
+        node = ethers_core::utils::keccak256([node, label_hash].concat());
+    }
+    Ok(node)
+}
+
+/// Decode an address returned by `call`, treating the zero address as "not set"
+pub fn decode_ens_address(data: &[u8], call: &str) -> Result<Option<H160>, String> {
+    let tokens = ethabi::decode(&[ParamType::Address], data)
+        .map_err(|e| format!("Invalid {} return data: {}", call, e))?;
+    match tokens.first() {
+        Some(Token::Address(address)) if address.is_zero() => Ok(None),
+        Some(Token::Address(address)) => Ok(Some(*address)),
+        _ => Err(format!("{} did not return an address", call)),
+    }
+}
+
+/// Nonce for the next send: `nonce_override` once checked against the confirmed nonce,
+/// otherwise the next pending one. `fetch_nonce` fetches the nonce at a block tag.
+pub async fn choose_nonce<F, Fut>(
+    nonce_override: Option<u64>,
+    fetch_nonce: F,
+) -> Result<u64, String>
+where
+    F: Fn(BlockTag) -> Fut,
+    Fut: Future<Output = Result<Nat, String>>,
+{
+    let Some(nonce) = nonce_override else {
+        return nonce_to_u64(&fetch_nonce(BlockTag::Pending).await?);
+    };
+    let confirmed_nonce = nonce_to_u64(&fetch_nonce(BlockTag::Latest).await?)?;
+    if nonce < confirmed_nonce {
+        return Err(format!(
+            "Nonce {} is already confirmed (current nonce is {})",
+            nonce, confirmed_nonce
+        ));
+    }
+    Ok(nonce)
+}
+
+/// Resolve `name` through the ENS `registry`, making each `eth_call` with `call(to, data)`:
+/// the registry's `resolver(node)`, then that resolver's `addr(node)`
+pub async fn resolve_ens_with<F, Fut>(
+    name: &str,
+    registry: &str,
+    mut call: F,
+) -> Result<String, String>
+where
+    F: FnMut(String, Vec<u8>) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, String>>,
+{
+    let node = ens_namehash(name)?;
+
+    let mut data = ethers_core::utils::id("resolver(bytes32)").to_vec();
+    data.extend(ethabi::encode(&[Token::FixedBytes(node.to_vec())]));
+    let data = call(registry.to_string(), data).await?;
+    let resolver = decode_ens_address(&data, "resolver()")?.ok_or("Name has no resolver")?;
+
+    let mut data = ethers_core::utils::id("addr(bytes32)").to_vec();
+    data.extend(ethabi::encode(&[Token::FixedBytes(node.to_vec())]));
+    let data = call(format!("{:?}", resolver), data).await?;
+    let address = decode_ens_address(&data, "addr()")?.ok_or("Name has no address record")?;
+    Ok(ethers_core::utils::to_checksum(&address, None))
+}
+
+/// The payout address for `recipient`: unchanged unless it looks like an ENS name and
+/// resolution is configured for `dest_chain`, in which case the name's `addr` record
+pub async fn resolve_recipient(recipient: &str, dest_chain: &str) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    if !looks_like_ens_name(recipient) {
+        return Ok(recipient.to_string());
+    }
+    let registry = ENS_REGISTRIES.with(|registries| registries.borrow().get(dest_chain).cloned());
+    match registry {
+        Some(registry) => {
+            let address = chain_service()?
+                .resolve_ens_name(recipient, &registry, dest_chain)
+                .await
+                .map_err(|e| format!("Could not resolve ENS name {}: {}", recipient, e))?;
+            ic_cdk::println!("🔎 Resolved {} to {}", recipient, address);
+            Ok(address)
+        }
+        None => Ok(recipient.to_string()),
+    }
+}
+
+/// Parse an EVM address, rejecting the zero address
+pub fn validate_eth_address(address: &str) -> Result<H160, String> {
+    let parsed = H160::from_str(address).map_err(|e| format!("Invalid address format: {}", e))?;
+    if parsed.is_zero() {
+        return Err("Recipient is the zero address".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Normalize an EVM address to its EIP-55 checksum form, accepting any letter case
+pub fn to_checksummed(address: &str) -> Result<String, String> {
+    let parsed = H160::from_str(address).map_err(|e| format!("Invalid address format: {}", e))?;
+    Ok(ethers_core::utils::to_checksum(&parsed, None))
+}
+
+/// Check a bridge payout recipient: a valid, non-zero address on the recipient allowlist
+/// (when enabled) that (unless disabled with `set_reject_self_recipient`) is not the
+/// canister's own address on `dest_chain`
+pub async fn validate_recipient(address: &str, dest_chain: &str) -> Result<(), String> {
+    let recipient = validate_eth_address(address)?;
+    check_recipient_allowed(&recipient)?;
+
+    if reject_self_recipient() {
+        let own_address = get_canister_ethereum_address(dest_chain).await?;
+        check_not_own_address(&recipient, &own_address)?;
+    }
+    Ok(())
+}
+
+/// Reject `recipient` when it is the canister's own address `own_address`
+fn check_not_own_address(recipient: &H160, own_address: &str) -> Result<(), String> {
+    if H160::from_str(own_address).ok() == Some(*recipient) {
+        return Err("Recipient is the bridge's own address".to_string());
+    }
+    Ok(())
+}
+
+const CONTRACT_ADDRESS_HEX: &str = "0x99a79158A40E4BEF8Beb3AcFAE893e62C45034E8";
+const EIP1559_TX_ID: u8 = 2;
+// First byte of an RLP-encoded list
+const RLP_LIST_PREFIX: u8 = 0xc0;
+
+/// `rlp` as a typed EIP-1559 payload with exactly one leading `0x02`. ethers 2 returns the
+/// bare field list from `rlp()`/`rlp_signed()`, but an encoding that already carries the
+/// type byte is left as is rather than prefixed twice.
+pub fn typed_eip1559_payload(rlp: &[u8]) -> Result<Vec<u8>, String> {
+    match rlp.first() {
+        Some(&EIP1559_TX_ID) => Ok(rlp.to_vec()),
+        Some(&first) if first >= RLP_LIST_PREFIX => {
+            let mut payload = Vec::with_capacity(rlp.len() + 1);
+            payload.push(EIP1559_TX_ID);
+            payload.extend_from_slice(rlp);
+            Ok(payload)
+        }
+        _ => Err("Transaction encoding is not an RLP list".to_string()),
+    }
+}
+
+/// A transaction count returned by the EVM RPC canister as a `u64` nonce
+pub fn nonce_to_u64(nonce: &Nat) -> Result<u64, String> {
+    nonce
+        .0
+        .to_u64()
+        .ok_or_else(|| format!("Nonce {} does not fit in u64", nonce))
+}
+
+/// Per-chain configuration used when building, signing and sending EVM transactions.
+///
+/// `ecdsa_key_name` selects the threshold ECDSA key used for this chain. Addresses are
+/// derived from the key, so two chains configured with different key names will have
+/// different canister (and per-caller) addresses, and funds sent to one are not
+/// reachable through the other.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub rpc_url: String,
+    pub ecdsa_key_name: String,
+    /// Bridge contract on this chain; payouts addressed to it carry provenance calldata
+    pub evm_contract: Option<String>,
+    /// Confirmations (including the inclusion block) after which a transaction is final
+    pub finality_blocks: u64,
+    /// Multiplier (at least 1.0) applied to the gas limit of every send; unset uses the
+    /// chain's default, which is raised for known L2s
+    pub gas_limit_grace: Option<f64>,
+}
+
+impl ChainConfig {
+    pub fn gas_limit_grace(&self) -> f64 {
+        self.gas_limit_grace
+            .unwrap_or_else(|| default_gas_limit_grace(self.chain_id))
+    }
+}
+
+// L2s such as Base account for L1 data costs differently, so a gas limit that is enough
+// on L1 can fall short there; their sends get this much headroom by default
+const L2_GAS_LIMIT_GRACE: f64 = 1.2;
+const L2_CHAIN_IDS: [u64; 2] = [8453, 84532];
+
+fn default_gas_limit_grace(chain_id: u64) -> f64 {
+    if L2_CHAIN_IDS.contains(&chain_id) {
+        L2_GAS_LIMIT_GRACE
+    } else {
+        1.0
+    }
+}
+
+/// `gas_limit` raised by `grace`, rounded up
+pub fn apply_gas_limit_grace(gas_limit: u128, grace: f64) -> u128 {
+    if grace <= 1.0 {
+        return gas_limit;
+    }
+    (gas_limit as f64 * grace).ceil() as u128
+}
+
+// Default finality depths. Holesky is an L1 with ~12s blocks and 12 blocks is past any
+// practical reorg. Base produces ~2s blocks and its sequencer can still reorg blocks that
+// are not yet settled on L1, so it waits for more blocks for a similar margin.
+const HOLESKY_FINALITY_BLOCKS: u64 = 12;
+const BASE_FINALITY_BLOCKS: u64 = 60;
+
+/// Origin of a bridged payout, appended as calldata when paying the bridge contract
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    pub source_account: String,
+    pub event_id: String,
+}
+
+/// ABI-encode provenance as `(string source_account, string event_id)`
+pub fn provenance_call_data(provenance: &Provenance) -> Vec<u8> {
+    ethabi::encode(&[
+        Token::String(provenance.source_account.clone()),
+        Token::String(provenance.event_id.clone()),
+    ])
+}
+
+thread_local! {
+    static CHAIN_CONFIGS: RefCell<HashMap<u64, ChainConfig>> = RefCell::new(HashMap::new());
+}
+
+pub fn chain_configs_snapshot() -> HashMap<u64, ChainConfig> {
+    CHAIN_CONFIGS.with(|configs| configs.borrow().clone())
+}
+
+pub fn restore_chain_configs(configs: HashMap<u64, ChainConfig>) {
+    CHAIN_CONFIGS.with(|current| *current.borrow_mut() = configs);
+}
+
+// Built-in configuration used for chains that have not been registered
+fn default_chain_config(dest_chain: &str) -> ChainConfig {
+    let (_, ecdsa_key) = get_network_config();
+    match dest_chain {
+        "8453" => ChainConfig {
+            chain_id: 8453,
+            rpc_url: "https://base.drpc.org".to_string(),
+            ecdsa_key_name: ecdsa_key.to_string(),
+            evm_contract: None,
+            finality_blocks: BASE_FINALITY_BLOCKS,
+            gas_limit_grace: None,
+        },
+        _ => ChainConfig {
+            chain_id: 17000,
+            rpc_url: "https://ethereum-holesky-rpc.publicnode.com".to_string(),
+            ecdsa_key_name: ecdsa_key.to_string(),
+            evm_contract: None,
+            finality_blocks: HOLESKY_FINALITY_BLOCKS,
+            gas_limit_grace: None,
+        }, // Default to Holesky
+    }
+}
+
+/// Look up the configuration for a destination chain, falling back to the built-in defaults
+pub fn get_chain_config(dest_chain: &str) -> ChainConfig {
+    let registered = dest_chain.parse::<u64>().ok().and_then(|chain_id| {
+        CHAIN_CONFIGS.with(|configs| configs.borrow().get(&chain_id).cloned())
+    });
+
+    registered.unwrap_or_else(|| default_chain_config(dest_chain))
+}
+
+/// Reject a chain config with a zero chain id or an RPC URL that is not http(s) with a host
+pub fn validate_chain_config(config: &ChainConfig) -> Result<(), String> {
+    if config.chain_id == 0 {
+        return Err("Chain id must be non-zero".to_string());
+    }
+    let url = url::Url::parse(&config.rpc_url)
+        .map_err(|e| format!("Invalid RPC URL {}: {}", config.rpc_url, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!(
+            "Invalid RPC URL {}: expected an http(s) URL with a host",
+            config.rpc_url
+        ));
+    }
+    if let Some(grace) = config.gas_limit_grace {
+        if !grace.is_finite() || grace < 1.0 {
+            return Err(format!(
+                "gas_limit_grace must be at least 1.0, got {}",
+                grace
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Register the config for `config.chain_id`, replacing any earlier one. Returns the
+/// replaced config so the change can be audited.
+#[update]
+pub fn register_chain_config(config: ChainConfig) -> Result<Option<ChainConfig>, String> {
+    require_controller()?;
+    insert_chain_config(config)
+}
+
+// Validate and store `config`, returning the config it replaced
+fn insert_chain_config(config: ChainConfig) -> Result<Option<ChainConfig>, String> {
+    validate_chain_config(&config)?;
+
+    let previous =
+        CHAIN_CONFIGS.with(|configs| configs.borrow_mut().insert(config.chain_id, config));
+    if let Some(previous) = &previous {
+        ic_cdk::println!(
+            "Replaced config for chain {}: {:?}",
+            previous.chain_id,
+            previous
+        );
+    }
+    Ok(previous)
+}
+
+/// ERC-20 token the bridge pays out on a destination chain
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TokenConfig {
+    pub dest_chain: String,
+    pub symbol: String,
+    pub address: String,
+    pub decimals: u8,
+}
+
+thread_local! {
+    // Keyed by (canonical chain id, upper-case symbol)
+    static TOKEN_CONFIGS: RefCell<HashMap<(String, String), TokenConfig>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn token_configs_snapshot() -> HashMap<(String, String), TokenConfig> {
+    TOKEN_CONFIGS.with(|configs| configs.borrow().clone())
+}
+
+pub fn restore_token_configs(configs: HashMap<(String, String), TokenConfig>) {
+    TOKEN_CONFIGS.with(|current| *current.borrow_mut() = configs);
+}
+
+pub fn get_token_config(dest_chain: &str, symbol: &str) -> Option<TokenConfig> {
+    TOKEN_CONFIGS.with(|configs| {
+        configs
+            .borrow()
+            .get(&(dest_chain.to_string(), symbol.to_uppercase()))
+            .cloned()
+    })
+}
+
+/// What a bridge payout sends: the chain's native asset or a registered ERC-20 token
+#[derive(Clone, Debug)]
+pub enum PayoutKind {
+    Native,
+    Erc20(TokenConfig),
+}
+
+/// Route a lock event's `dest_token` on `dest_chain`: none (or the native symbol) pays the
+/// native asset, a registered symbol its token, and anything else is an error
+pub fn payout_kind(dest_chain: &str, dest_token: Option<&str>) -> Result<PayoutKind, String> {
+    match dest_token.map(str::trim) {
+        None | Some("") => Ok(PayoutKind::Native),
+        Some(symbol) if symbol.eq_ignore_ascii_case(NATIVE_SYMBOL) => Ok(PayoutKind::Native),
+        Some(symbol) => get_token_config(dest_chain, symbol)
+            .map(PayoutKind::Erc20)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown destination token {} on chain {}",
+                    symbol, dest_chain
+                )
+            }),
+    }
+}
+
+#[update]
+pub fn register_token_config(config: TokenConfig) -> Result<(), String> {
+    require_controller()?;
+
+    let mut config = config;
+    config.dest_chain = normalize_chain(&config.dest_chain)?;
+    config.symbol = config.symbol.to_uppercase();
+    config.address =
+        to_checksummed(&config.address).map_err(|e| format!("Invalid token address: {}", e))?;
+
+    TOKEN_CONFIGS.with(|configs| {
+        configs
+            .borrow_mut()
+            .insert((config.dest_chain.clone(), config.symbol.clone()), config);
+    });
+    Ok(())
+}
+
+// Chains known by name: (alias, chain id, is testnet)
+const KNOWN_CHAINS: [(&str, u64, bool); 3] = [
+    ("holesky", 17000, true),
+    ("base", 8453, false),
+    ("base-sepolia", 84532, true),
+];
+
+// Chains served by the built-in defaults without any registration
+const DEFAULT_CHAIN_IDS: [u64; 2] = [17000, 8453];
+
+/// A destination chain the bridge can pay out on, as reported to front-ends
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SupportedChain {
+    pub chain_id: u64,
+    pub name: String,
+    pub rpc_url: String,
+    pub evm_contract: Option<String>,
+    pub is_testnet: bool,
+}
+
+/// Whether a chain known by name is a testnet; `None` for chains known only by id
+pub fn chain_is_testnet(chain_id: u64) -> Option<bool> {
+    KNOWN_CHAINS
+        .iter()
+        .find(|(_, id, _)| *id == chain_id)
+        .map(|(_, _, is_testnet)| *is_testnet)
+}
+
+/// Whether the bridge can pay out on `chain_id`: a built-in default or a registered chain
+pub fn is_supported_chain(chain_id: u64) -> bool {
+    DEFAULT_CHAIN_IDS.contains(&chain_id)
+        || CHAIN_CONFIGS.with(|configs| configs.borrow().contains_key(&chain_id))
+}
+
+/// The built-in default chains plus every registered chain config, ordered by chain id
+#[ic_cdk::query]
+pub fn get_supported_chains() -> Vec<SupportedChain> {
+    let mut chain_ids: Vec<u64> =
+        CHAIN_CONFIGS.with(|configs| configs.borrow().keys().copied().collect());
+    chain_ids.extend(DEFAULT_CHAIN_IDS);
+    chain_ids.sort_unstable();
+    chain_ids.dedup();
+
+    chain_ids
+        .into_iter()
+        .map(|chain_id| {
+            let config = get_chain_config(&chain_id.to_string());
+            let known = KNOWN_CHAINS.iter().find(|(_, id, _)| *id == chain_id);
+            SupportedChain {
+                chain_id,
+                name: known.map_or_else(|| chain_id.to_string(), |(name, _, _)| name.to_string()),
+                rpc_url: config.rpc_url,
+                evm_contract: config.evm_contract,
+                is_testnet: known.is_some_and(|(_, _, is_testnet)| *is_testnet),
+            }
+        })
+        .collect()
+}
+
+/// Map a destination chain given by name or id to its canonical numeric chain id.
+///
+/// Accepts the known aliases ("holesky", "base", "base-sepolia", case-insensitive) and
+/// decimal chain ids, but only for chains with a built-in default or a registered config
+/// (see `is_supported_chain`), so no chain silently falls back to Holesky's config.
+pub fn normalize_chain(input: &str) -> Result<String, String> {
+    let trimmed = input.trim().to_ascii_lowercase();
+    let chain_id = match KNOWN_CHAINS.iter().find(|(name, _, _)| *name == trimmed) {
+        Some((_, chain_id, _)) => *chain_id,
+        None => trimmed
+            .parse::<u64>()
+            .ok()
+            .filter(|_| trimmed.chars().all(|c| c.is_ascii_digit()))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown destination chain {:?}; expected a chain id or one of holesky, base, base-sepolia",
+                    input
+                )
+            })?,
+    };
+    if !is_supported_chain(chain_id) {
+        return Err(format!(
+            "Chain {} has no registered config; register it with register_chain_config",
+            chain_id
+        ));
+    }
+    Ok(chain_id.to_string())
+}
+
+// Helper function to get RPC configuration based on destination chain
+fn get_rpc_config(dest_chain: &str) -> (u64, String) {
+    let config = get_chain_config(dest_chain);
+    (config.chain_id, config.rpc_url)
+}
+
+impl ChainService {
+    async fn pubkey_and_signature(
+        &self,
+        tx_hash: Vec<u8>,
+        dest_chain: &str,
+    ) -> Result<(Vec<u8>, SignWithEcdsaResponse), String> {
+        let public_key_response = get_ecdsa_public_key(dest_chain).await?; // now a Result
+
+        let (signature_response,) = retry_signing(sign_attempts(), SIGN_RETRY_DELAY, || {
+            sign_with_ecdsa(SignWithEcdsaArgument {
+                message_hash: tx_hash.clone(),
+                key_id: key_id(dest_chain),
+                ..Default::default()
+            })
+        })
+        .await?;
+
+        Ok((public_key_response.public_key, signature_response))
+    }
+
+    async fn caller_pubkey_and_signature(
+        &self,
+        tx_hash: Vec<u8>,
+        dest_chain: &str,
+    ) -> Result<(Vec<u8>, SignWithEcdsaResponse), String> {
+        let public_key_response = get_caller_ecdsa_public_key(dest_chain).await?;
+
+        let (signature_response,) = retry_signing(sign_attempts(), SIGN_RETRY_DELAY, || {
+            sign_with_ecdsa(SignWithEcdsaArgument {
+                message_hash: tx_hash.clone(),
+                derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
+                key_id: key_id(dest_chain),
+                ..Default::default()
+            })
+        })
+        .await?;
+
+        Ok((public_key_response.public_key, signature_response))
+    }
+
+    /// Fetch transaction count (nonce) for your IC Ethereum address (from secp256k1 pubkey)
+    /// as of `block_tag`; sends use `Pending` so queued transactions are counted
+    pub async fn fetch_tx_nonce(
+        &self,
+        dest_chain: &str,
+        block_tag: BlockTag,
+    ) -> Result<Nat, String> {
+        // The address depends on the chain's key name, so derive it rather than using a constant
+        let canister_address = get_canister_ethereum_address(dest_chain).await?;
+        ic_cdk::println!("canister_address {}", canister_address);
+        let get_transaction_count_args = GetTransactionCountArgs {
+            address: canister_address,
+            block: block_tag,
+        };
+
+        self.fetch_transaction_count(dest_chain, get_transaction_count_args)
+            .await
+    }
+
+    /// Fetch transaction count (nonce) for caller's address using derivation path
+    pub async fn fetch_caller_tx_nonce(
+        &self,
+        dest_chain: &str,
+        block_tag: BlockTag,
+    ) -> Result<Nat, String> {
+        // Get caller's Ethereum address
+        let caller_address = get_caller_ethereum_address(dest_chain).await?;
+        ic_cdk::println!("caller_address {}", caller_address);
+        
+        let get_transaction_count_args = GetTransactionCountArgs {
+            address: caller_address,
+            block: block_tag,
+        };
+
+        self.fetch_transaction_count(dest_chain, get_transaction_count_args)
+            .await
+    }
+
+    // eth_getTransactionCount for `args`, refetched up to `nonce_fetch_retries()` times
+    // while providers disagree
+    async fn fetch_transaction_count(
+        &self,
+        dest_chain: &str,
+        args: GetTransactionCountArgs,
+    ) -> Result<Nat, String> {
+        settle_transaction_count(&args, nonce_fetch_retries(), NONCE_RETRY_DELAY, || {
+            self.request_transaction_count(dest_chain, &args)
+        })
+        .await
+    }
+
+    async fn request_transaction_count(
+        &self,
+        dest_chain: &str,
+        args: &GetTransactionCountArgs,
+    ) -> Result<MultiGetTransactionCountResult, String> {
+        // Prepare cycles amount to pay for the call (adjust as necessary)
+        let cycles: u128 = 200_000_000_000u128;
+
+        // Get RPC configuration based on destination chain
+        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
+        ic_cdk::println!("Using chain_id: {} and rpc_url: {}", chain_id, rpc_url);
+
+        // The principal (canister ID) of the EVM RPC canister
+        let evm_canister_id = self.evm_rpc.0;
+
+        // Make cross-canister call with cycles payment
+        let (transaction_result,) = call_with_payment128::<
+            (
+                RpcServices,
+                Option<crate::evm_rpc_bindings::RpcConfig>,
+                GetTransactionCountArgs,
+            ),
+            (MultiGetTransactionCountResult,),
+        >(
+            evm_canister_id,
+            "eth_getTransactionCount",
+            (
+                RpcServices::Custom {
+                    chainId: chain_id,
+                    services: vec![RpcApi {
+                        url: rpc_url,
+                        headers: None,
+                    }],
+                },
+                None,
+                args.clone(),
+            ),
+            cycles,
+        )
+        .await
+        .map_err(|e| {
+            rpc_call_error(
+                "eth_getTransactionCount",
+                cycles,
+                "Failed to get transaction count",
+                e,
+            )
+        })?;
+
+        Ok(transaction_result)
+    }
+
+    pub async fn send_eth_evm(
+        &self,
         to: String,
         amount: String,
         dest_chain: String,
+        provenance: Option<Provenance>,
     ) -> Result<String, String> {
-        use ethers_core::types::U256;
+        // Convert ETH decimal amount to wei for transaction
+        let eth_amount: f64 = amount
+            .parse()
+            .map_err(|e| format!("Invalid ETH amount: {}", e))?;
+        let amount_wei = eth_to_wei_f64(eth_amount)?;
+
+        self.send_eth_evm_wei(to, amount_wei, dest_chain, provenance, None, None)
+            .await
+    }
+
+    /// Send exactly `amount_wei` of the native asset to `to`; fields set in `gas_overrides`
+    /// replace the estimated gas limit and fees, and `nonce_override` pins the nonce instead
+    /// of taking the next pending one
+    pub async fn send_eth_evm_wei(
+        &self,
+        to: String,
+        amount_wei: U256,
+        dest_chain: String,
+        provenance: Option<Provenance>,
+        gas_overrides: Option<GasOverrides>,
+        nonce_override: Option<u64>,
+    ) -> Result<String, String> {
+        ic_cdk::println!("dest_chain {}", dest_chain);
+
+        // 4. Get nonce for from address, counting our own queued transactions
+        let nonce = Nat::from(self.next_nonce(nonce_override, &dest_chain).await?);
+        ic_cdk::println!("Nonce for address: {}", nonce);
+
+        ic_cdk::println!("AMOUNT {} wei", amount_wei);
+
+        // 5. Estimate gas fees from the chain's live base fee
+        let (_, max_fee_per_gas, max_priority_fee_per_gas) =
+            estimate_transaction_fees_for_chain(&dest_chain).await;
+        let gas_limit = native_gas_limit(
+            self.is_contract(&to, &dest_chain).await,
+            CONTRACT_TRANSFER_GAS_LIMIT,
+        );
+        let gas_limit =
+            apply_gas_limit_grace(gas_limit, get_chain_config(&dest_chain).gas_limit_grace());
+        let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) = apply_gas_overrides(
+            (gas_limit, max_fee_per_gas, max_priority_fee_per_gas),
+            gas_overrides.as_ref(),
+        )?;
+
+        // Get chain ID for the transaction
+        let (chain_id, _) = get_rpc_config(&dest_chain);
+        ic_cdk::println!("Building transaction for chain_id: {}", chain_id);
+
+        // Only the configured bridge contract receives provenance; EOAs get a plain transfer
+        let is_bridge_contract = get_chain_config(&dest_chain)
+            .evm_contract
+            .is_some_and(|contract| contract.eq_ignore_ascii_case(&to));
+        let data = match provenance {
+            Some(provenance) if is_bridge_contract => {
+                Some(Bytes::from(provenance_call_data(&provenance)))
+            }
+            _ => None,
+        };
+
+        // 6. Build the EIP-1559 transaction request
+        let tx = Eip1559TransactionRequest {
+            from: None,
+            to: Some(validate_eth_address(&to)?.into()),
+            nonce: Some(U256::from(nonce_to_u64(&nonce)?)),
+            gas: Some(U256::from(gas_limit)),
+            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
+            max_priority_fee_per_gas: Some(U256::from(max_priority_fee_per_gas)),
+            value: Some(amount_wei),
+            data,
+            access_list: vec![].into(),
+            chain_id: Some(U64::from(chain_id)),
+        };
+
+        self.sign_and_submit(tx, &dest_chain).await
+    }
+
+    /// Sign an EIP-1559 transaction with the canister key for `dest_chain` and broadcast it
+    async fn sign_and_submit(
+        &self,
+        tx: Eip1559TransactionRequest,
+        dest_chain: &str,
+    ) -> Result<String, String> {
+        use ethers_core::types::U256;
+
+        // Contract calls that would revert are dropped here rather than paying gas on-chain
+        if tx.data.is_some() && simulate_before_send() {
+            self.simulate_transaction(&tx, dest_chain).await?;
+        }
+
+        // 7. RLP encode the unsigned transaction and prefix with EIP1559 tx id (0x02)
+        let unsigned_tx = typed_eip1559_payload(&tx.rlp())?;
+
+        // 8. Generate the transaction hash to sign (keccak256 of the unsigned_tx)
+        let tx_hash = ethers_core::utils::keccak256(&unsigned_tx);
+
+        // 9. Sign the transaction hash with IC ECDSA key
+        let (public_key_bytes, signature) = self
+            .pubkey_and_signature(tx_hash.to_vec(), dest_chain)
+            .await?;
+
+        // 10. Recover y parity (v) from signature
+        check_signature_len(&signature.signature)?;
+        let y_parity = y_parity(&tx_hash, &signature.signature, &public_key_bytes)?;
+
+        // 11. Create ethers Signature struct with r,s,v
+        let sig = ethers_core::types::Signature {
+            r: U256::from_big_endian(&signature.signature[0..32]),
+            s: U256::from_big_endian(&signature.signature[32..64]),
+            v: y_parity as u64,
+        };
+        verify_signer(&tx_hash, &sig, &pubkey_bytes_to_address(&public_key_bytes)?)?;
+
+        // 12. RLP encode the signed transaction and prefix with tx id
+        let signed_tx = typed_eip1559_payload(&tx.rlp_signed(&sig))?;
+
+        let raw_tx_hex = format!("0x{}", hex::encode(&signed_tx));
+
+        ic_cdk::println!("Raw signed transaction hex: {}", raw_tx_hex);
+
+        // 13. Send the raw transaction using your evm_rpc canister's eth_sendRawTransaction
+        let send_status = self
+            .send_raw_transaction(raw_tx_hex.clone(), dest_chain)
+            .await?;
+
+        // 14. Parse result from send
+        match send_status {
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(opt_tx_hash)) => {
+                if let Some(tx_hash) = opt_tx_hash {
+                    {
+                        let mut hash = TX_HASH.write().unwrap();
+                        *hash = Some(tx_hash.clone());
+                    }
+                    record_raw_tx(&tx_hash, raw_tx_hex, dest_chain);
+
+                    ic_cdk::println!("✅ Transaction sent successfully, tx hash: {:?}", tx_hash);
+                    Ok(tx_hash)
+                } else {
+                    Err("Error: transaction hash not found in the response".to_string())
+                }
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow) => {
+                Err("Error: nonce too low".to_string())
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooHigh) => {
+                if let Some(nonce) = tx.nonce {
+                    STUCK_NONCES.with(|nonces| {
+                        nonces
+                            .borrow_mut()
+                            .insert((nonce.low_u64(), dest_chain.to_string()))
+                    });
+                }
+                Err("Error: nonce too high".to_string())
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::InsufficientFunds) => {
+                Err("Error: insufficient funds".to_string())
+            }
+            SendRawTransactionResult::Err(rpc_error) => {
+                Err(too_few_cycles_error("eth_sendRawTransaction", &rpc_error)
+                    .unwrap_or_else(|| format!("RPC error sending transaction: {:?}", rpc_error)))
+            }
+        }
+    }
+
+    /// Broadcast signed transaction bytes with `eth_sendRawTransaction`, resolving any
+    /// disagreement between providers
+    async fn send_raw_transaction(
+        &self,
+        raw_tx_hex: String,
+        dest_chain: &str,
+    ) -> Result<SendRawTransactionResult, String> {
+        let cycles_to_pay: u128 = 600_000_000_000;
+        let expected_hash = signed_tx_hash(&raw_tx_hex)?;
+
+        // Get RPC configuration for sending the transaction
+        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
+        ic_cdk::println!(
+            "Sending transaction to chain_id: {} with rpc_url: {}",
+            chain_id,
+            rpc_url
+        );
+
+        let (send_result,) = call_with_payment128::<
+            (
+                RpcServices,
+                Option<crate::evm_rpc_bindings::RpcConfig>,
+                String,
+            ),
+            (MultiSendRawTransactionResult,),
+        >(
+            self.evm_rpc.0, // the canister principal of your evm_rpc canister
+            "eth_sendRawTransaction",
+            (
+                RpcServices::Custom {
+                    chainId: chain_id,
+                    services: vec![RpcApi {
+                        url: rpc_url,
+                        headers: None,
+                    }],
+                },
+                None,
+                raw_tx_hex,
+            ),
+            cycles_to_pay, // send cycles for payment here
+        )
+        .await
+        .map_err(|e| {
+            rpc_call_error(
+                "eth_sendRawTransaction",
+                cycles_to_pay,
+                "Failed to send raw transaction",
+                e,
+            )
+        })?;
+
+        match send_result {
+            MultiSendRawTransactionResult::Consistent(send_status) => Ok(send_status),
+            MultiSendRawTransactionResult::Inconsistent(results) => {
+                resolve_inconsistent_send(results, &expected_hash)
+            }
+        }
+    }
+
+    /// Resubmit the exact signed bytes stored for `tx_hash`, e.g. after the node dropped it
+    /// from its mempool. A node that still holds the transaction counts as success.
+    pub async fn rebroadcast(&self, tx_hash: &str, dest_chain: &str) -> Result<String, String> {
+        let record = SENT_TXS
+            .with(|txs| txs.borrow().get(tx_hash).cloned())
+            .ok_or_else(|| format!("No stored raw transaction for {}", tx_hash))?;
+        if record.dest_chain != dest_chain {
+            return Err(format!(
+                "Transaction {} was sent on chain {}, not {}",
+                tx_hash, record.dest_chain, dest_chain
+            ));
+        }
+
+        ic_cdk::println!("📡 Rebroadcasting {} on chain {}", tx_hash, dest_chain);
+        let send_status = self
+            .send_raw_transaction(record.raw_tx_hex, dest_chain)
+            .await?;
+        match send_status {
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(_)) => {
+                Ok(tx_hash.to_string())
+            }
+            SendRawTransactionResult::Err(rpc_error) if is_already_known(&rpc_error) => {
+                ic_cdk::println!("Transaction {} is already known to the node", tx_hash);
+                Ok(tx_hash.to_string())
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow) => Err(
+                "Error: nonce too low; the transaction (or a replacement) was likely mined"
+                    .to_string(),
+            ),
+            SendRawTransactionResult::Ok(status) => Err(format!("Error: {:?}", status)),
+            SendRawTransactionResult::Err(rpc_error) => Err(format!(
+                "RPC error rebroadcasting transaction: {:?}",
+                rpc_error
+            )),
+        }
+    }
+
+    // The pinned nonce (refused if already confirmed) or the next pending one
+    async fn next_nonce(
+        &self,
+        nonce_override: Option<u64>,
+        dest_chain: &str,
+    ) -> Result<u64, String> {
+        choose_nonce(nonce_override, |block_tag| {
+            self.fetch_tx_nonce(dest_chain, block_tag)
+        })
+        .await
+    }
+
+    /// Replace whatever is stuck at `nonce` with a 0-value self-send at an aggressive fee
+    pub async fn clear_nonce(&self, nonce: u64, dest_chain: String) -> Result<String, String> {
+        let confirmed_nonce =
+            nonce_to_u64(&self.fetch_tx_nonce(&dest_chain, BlockTag::Latest).await?)?;
+        if nonce < confirmed_nonce {
+            return Err(format!(
+                "Nonce {} is already confirmed (current nonce is {})",
+                nonce, confirmed_nonce
+            ));
+        }
+
+        let canister_address = get_canister_ethereum_address(&dest_chain).await?;
+        let (chain_id, _) = get_rpc_config(&dest_chain);
+        let tx = build_clear_nonce_tx(&canister_address, nonce, chain_id, &dest_chain)?;
+        ic_cdk::println!("Clearing nonce {} on chain {}", nonce, chain_id);
+
+        let tx_hash = self.sign_and_submit(tx, &dest_chain).await?;
+        STUCK_NONCES.with(|nonces| {
+            nonces
+                .borrow_mut()
+                .retain(|(stuck, chain)| *chain != dest_chain || *stuck > nonce)
+        });
+        Ok(tx_hash)
+    }
+
+    pub async fn transfer_eth(
+        &self,
+        to: String,
+        amount: String,
+        dest_chain: String,
+    ) -> Result<String, String> {
+        use ethers_core::types::U256;
+
+        ic_cdk::println!("🔄 transfer_eth - dest_chain: {}", dest_chain);
+        ic_cdk::println!("🔄 transfer_eth - caller: {}", ic_cdk::api::caller());
+        
+        // Get caller's Ethereum address for logging
+        let caller_eth_address = get_caller_ethereum_address(&dest_chain).await?;
+        ic_cdk::println!("🔄 transfer_eth - caller_eth_address: {}", caller_eth_address);
+
+        // Get nonce for caller's address
+        let nonce = self
+            .fetch_caller_tx_nonce(&dest_chain, BlockTag::Pending)
+            .await?;
+        ic_cdk::println!("Caller nonce: {}", nonce);
+
+        ic_cdk::println!("AMOUNT {}", amount);
+
+        // Estimate gas fees based on destination chain
+        let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) =
+            estimate_transaction_fees_for_chain(&dest_chain).await;
+        let gas_limit =
+            apply_gas_limit_grace(gas_limit, get_chain_config(&dest_chain).gas_limit_grace());
+
+        ic_cdk::println!("Gas fees - limit: {}, max_fee: {}, priority_fee: {}", gas_limit, max_fee_per_gas, max_priority_fee_per_gas);
+
+        // Get chain ID for the transaction
+        let (chain_id, rpc_url) = get_rpc_config(&dest_chain);
+        ic_cdk::println!("Building transaction for chain_id: {} with RPC: {}", chain_id, rpc_url);
+
+        // Build the EIP-1559 transaction request
+        let tx = Eip1559TransactionRequest {
+            from: None,
+            to: Some(H160::from_str(&to).map_err(|e| format!("Invalid address format: {}", e))?.into()),
+            nonce: Some(U256::from(nonce_to_u64(&nonce)?)),
+            gas: Some(U256::from(gas_limit)),
+            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
+            max_priority_fee_per_gas: Some(U256::from(max_priority_fee_per_gas)),
+            value: Some({
+                // Convert ETH decimal amount to wei for transaction
+                let eth_amount: f64 = amount.parse().map_err(|e| format!("Invalid ETH amount: {}", e))?;
+                eth_to_wei_f64(eth_amount)?
+            }),
+            data: Default::default(),
+            access_list: vec![].into(),
+            chain_id: Some(U64::from(chain_id)),
+        };
+
+        // RLP encode the unsigned transaction and prefix with EIP1559 tx id (0x02)
+        let unsigned_tx = typed_eip1559_payload(&tx.rlp())?;
+
+        // Generate the transaction hash to sign (keccak256 of the unsigned_tx)
+        let tx_hash = ethers_core::utils::keccak256(&unsigned_tx);
+
+        // Sign the transaction hash with caller's IC ECDSA key (using derivation path)
+        let (public_key_bytes, signature) = self
+            .caller_pubkey_and_signature(tx_hash.to_vec(), &dest_chain)
+            .await?;
+
+        // Recover y parity (v) from signature
+        check_signature_len(&signature.signature)?;
+        let y_parity = y_parity(&tx_hash, &signature.signature, &public_key_bytes)?;
+
+        // Create ethers Signature struct with r,s,v
+        let sig = ethers_core::types::Signature {
+            r: U256::from_big_endian(&signature.signature[0..32]),
+            s: U256::from_big_endian(&signature.signature[32..64]),
+            v: y_parity as u64,
+        };
+        verify_signer(&tx_hash, &sig, &pubkey_bytes_to_address(&public_key_bytes)?)?;
+
+        // RLP encode the signed transaction and prefix with tx id
+        let signed_tx = typed_eip1559_payload(&tx.rlp_signed(&sig))?;
+
+        let raw_tx_hex = format!("0x{}", hex::encode(&signed_tx));
+
+        ic_cdk::println!("Raw signed transaction hex: {}", raw_tx_hex);
+
+        let cycles_to_pay: u128 = 600_000_000_000;
+        let expected_hash = signed_tx_hash(&raw_tx_hex)?;
+
+        // Get RPC configuration for sending the transaction
+        let (chain_id, rpc_url) = get_rpc_config(&dest_chain);
+        ic_cdk::println!(
+            "Sending transaction to chain_id: {} with rpc_url: {}",
+            chain_id,
+            rpc_url
+        );
+
+        // Send the raw transaction using evm_rpc canister's eth_sendRawTransaction
+        let (send_result,) = call_with_payment128::<
+            (
+                RpcServices,
+                Option<crate::evm_rpc_bindings::RpcConfig>,
+                String,
+            ),
+            (MultiSendRawTransactionResult,),
+        >(
+            self.evm_rpc.0,
+            "eth_sendRawTransaction",
+            (
+                RpcServices::Custom {
+                    chainId: chain_id,
+                    services: vec![RpcApi {
+                        url: rpc_url,
+                        headers: None,
+                    }],
+                },
+                None,
+                raw_tx_hex,
+            ),
+            cycles_to_pay,
+        )
+        .await
+        .map_err(|e| {
+            rpc_call_error(
+                "eth_sendRawTransaction",
+                cycles_to_pay,
+                "Failed to send raw transaction",
+                e,
+            )
+        })?;
+
+        // Parse result from send
+        let send_status = match send_result {
+            MultiSendRawTransactionResult::Consistent(send_status) => send_status,
+            MultiSendRawTransactionResult::Inconsistent(results) => {
+                resolve_inconsistent_send(results, &expected_hash)?
+            }
+        };
+        match send_status {
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(opt_tx_hash)) => {
+                if let Some(tx_hash) = opt_tx_hash {
+                    ic_cdk::println!(
+                        "✅ Transfer transaction sent successfully, tx hash: {:?}",
+                        tx_hash
+                    );
+                    Ok(tx_hash)
+                } else {
+                    Err("Error: transaction hash not found in the response".to_string())
+                }
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow) => {
+                Err("Error: nonce too low".to_string())
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooHigh) => {
+                Err("Error: nonce too high".to_string())
+            }
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::InsufficientFunds) => {
+                Err("Error: insufficient funds".to_string())
+            }
+            SendRawTransactionResult::Err(rpc_error) => {
+                Err(too_few_cycles_error("eth_sendRawTransaction", &rpc_error)
+                    .unwrap_or_else(|| format!("RPC error sending transaction: {:?}", rpc_error)))
+            }
+        }
+    }
+
+    /// Send a raw JSON-RPC request through the EVM RPC canister and return its `result` field
+    pub async fn json_rpc_request(
+        &self,
+        dest_chain: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        self.json_rpc_request_within(dest_chain, method, params, DEFAULT_RESPONSE_BYTES)
+            .await
+    }
+
+    /// `json_rpc_request` for a response of up to `max_response_bytes`
+    pub async fn json_rpc_request_within(
+        &self,
+        dest_chain: &str,
+        method: &str,
+        params: serde_json::Value,
+        max_response_bytes: u64,
+    ) -> Result<serde_json::Value, String> {
+        let json = self
+            .json_rpc_response(dest_chain, method, params, max_response_bytes)
+            .await?;
+        if let Some(error) = json.get("error") {
+            return Err(format!("{} returned an error: {}", method, error));
+        }
+        json.get("result")
+            .cloned()
+            .ok_or_else(|| format!("{} response has no result field", method))
+    }
+
+    // The whole JSON-RPC response, including any `error` object
+    async fn json_rpc_response(
+        &self,
+        dest_chain: &str,
+        method: &str,
+        params: serde_json::Value,
+        max_response_bytes: u64,
+    ) -> Result<serde_json::Value, String> {
+        let (_, rpc_url) = get_rpc_config(dest_chain);
+        let payload = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let cycles: u128 = 10_000_000_000;
+
+        let (result,) = call_with_payment128::<(RpcService, String, u64), (RequestResult,)>(
+            self.evm_rpc.0,
+            "request",
+            (
+                RpcService::Custom(RpcApi {
+                    url: rpc_url,
+                    headers: None,
+                }),
+                payload,
+                max_response_bytes,
+            ),
+            cycles,
+        )
+        .await
+        .map_err(|e| rpc_call_error(method, cycles, &format!("Failed to call {}", method), e))?;
+
+        let body = match result {
+            RequestResult::Ok(body) => body,
+            RequestResult::Err(error) => {
+                return Err(too_few_cycles_error(method, &error)
+                    .unwrap_or_else(|| format!("RPC error calling {}: {:?}", method, error)))
+            }
+        };
+
+        serde_json::from_str(&body)
+            .map_err(|e| format!("Invalid JSON-RPC response for {}: {}", method, e))
+    }
+
+    /// Dry-run `tx` from the canister's address with `eth_call`, failing with the decoded
+    /// revert reason if it would revert
+    pub async fn simulate_transaction(
+        &self,
+        tx: &Eip1559TransactionRequest,
+        dest_chain: &str,
+    ) -> Result<(), String> {
+        let from = get_canister_ethereum_address(dest_chain).await?;
+        let to = tx
+            .to
+            .as_ref()
+            .and_then(|to| to.as_address())
+            .ok_or("Cannot simulate a transaction without a destination address")?;
+        let data = tx
+            .data
+            .as_ref()
+            .map(|data| data.to_vec())
+            .unwrap_or_default();
+        let value = tx.value.unwrap_or_default();
+
+        let json = self
+            .json_rpc_response(
+                dest_chain,
+                "eth_call",
+                serde_json::json!([
+                    {
+                        "from": from,
+                        "to": format!("{:?}", to),
+                        "data": format!("0x{}", hex::encode(data)),
+                        "value": format!("{:#x}", value),
+                    },
+                    "latest"
+                ]),
+                DEFAULT_RESPONSE_BYTES,
+            )
+            .await?;
+        simulation_result(&json)
+    }
+
+    /// Fetch the native balance (in wei) of `address` on `dest_chain` as of `block_tag`
+    pub async fn fetch_balance(
+        &self,
+        address: &str,
+        dest_chain: &str,
+        block_tag: BlockTag,
+    ) -> Result<U256, String> {
+        let result = self
+            .json_rpc_request(
+                dest_chain,
+                "eth_getBalance",
+                balance_params(address, &block_tag),
+            )
+            .await?;
+
+        let quantity = result
+            .as_str()
+            .ok_or_else(|| format!("Unexpected eth_getBalance result: {}", result))?;
+        parse_hex_quantity(quantity)
+    }
+
+    /// Base fee (in wei) of the block after the latest one on `dest_chain`, via
+    /// `eth_feeHistory`. `finalized` trails the head by minutes and would underprice a fee
+    /// spike; `dynamic_max_fee` still doubles this for headroom.
+    pub async fn fetch_base_fee(&self, dest_chain: &str) -> Result<U256, String> {
+        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
+        let cycles: u128 = 10_000_000_000;
+
+        let (result,) = call_with_payment128::<
+            (
+                RpcServices,
+                Option<crate::evm_rpc_bindings::RpcConfig>,
+                FeeHistoryArgs,
+            ),
+            (MultiFeeHistoryResult,),
+        >(
+            self.evm_rpc.0,
+            "eth_feeHistory",
+            (
+                RpcServices::Custom {
+                    chainId: chain_id,
+                    services: vec![RpcApi {
+                        url: rpc_url,
+                        headers: None,
+                    }],
+                },
+                None,
+                FeeHistoryArgs {
+                    blockCount: Nat::from(1u8),
+                    newestBlock: BlockTag::Latest,
+                    rewardPercentiles: None,
+                },
+            ),
+            cycles,
+        )
+        .await
+        .map_err(|e| rpc_call_error("eth_feeHistory", cycles, "Failed to get fee history", e))?;
+
+        match result {
+            MultiFeeHistoryResult::Consistent(FeeHistoryResult::Ok(history)) => {
+                fee_history_base_fee(&history)
+            }
+            MultiFeeHistoryResult::Consistent(FeeHistoryResult::Err(error)) => {
+                Err(too_few_cycles_error("eth_feeHistory", &error)
+                    .unwrap_or_else(|| format!("Failed to get fee history: {:?}", error)))
+            }
+            MultiFeeHistoryResult::Inconsistent(results) => {
+                Err(format!("Inconsistent fee history: {:?}", results))
+            }
+        }
+    }
+
+    /// Latest block number on `dest_chain`
+    pub async fn fetch_block_number(&self, dest_chain: &str) -> Result<u64, String> {
+        let result = self
+            .json_rpc_request(dest_chain, "eth_blockNumber", serde_json::json!([]))
+            .await?;
+        let quantity = result
+            .as_str()
+            .ok_or_else(|| format!("Unexpected eth_blockNumber result: {}", result))?;
+        let block = parse_hex_quantity(quantity)?;
+        if block > U256::from(u64::MAX) {
+            return Err(format!("Block number {} does not fit in u64", block));
+        }
+        Ok(block.as_u64())
+    }
+
+    /// Block `tx_hash` was included in, or `None` while it has no receipt
+    pub async fn fetch_receipt_block(
+        &self,
+        tx_hash: &str,
+        dest_chain: &str,
+    ) -> Result<Option<u64>, String> {
+        let (chain_id, rpc_url) = get_rpc_config(dest_chain);
+        let cycles: u128 = 10_000_000_000;
+
+        let (receipt_result,) = call_with_payment128::<
+            (
+                RpcServices,
+                Option<crate::evm_rpc_bindings::RpcConfig>,
+                String,
+            ),
+            (MultiGetTransactionReceiptResult,),
+        >(
+            self.evm_rpc.0,
+            "eth_getTransactionReceipt",
+            (
+                RpcServices::Custom {
+                    chainId: chain_id,
+                    services: vec![RpcApi {
+                        url: rpc_url,
+                        headers: None,
+                    }],
+                },
+                None,
+                tx_hash.to_string(),
+            ),
+            cycles,
+        )
+        .await
+        .map_err(|e| {
+            rpc_call_error(
+                "eth_getTransactionReceipt",
+                cycles,
+                "Failed to get transaction receipt",
+                e,
+            )
+        })?;
+
+        let receipt = match receipt_result {
+            MultiGetTransactionReceiptResult::Consistent(GetTransactionReceiptResult::Ok(
+                receipt,
+            )) => receipt,
+            MultiGetTransactionReceiptResult::Consistent(GetTransactionReceiptResult::Err(
+                error,
+            )) => {
+                return Err(format!(
+                    "Failed to get receipt for {}: {:?}",
+                    tx_hash, error
+                ))
+            }
+            MultiGetTransactionReceiptResult::Inconsistent(results) => {
+                return Err(format!(
+                    "Inconsistent receipts for {}: {:?}",
+                    tx_hash, results
+                ))
+            }
+        };
+
+        receipt
+            .map(|receipt| {
+                receipt
+                    .blockNumber
+                    .0
+                    .to_u64()
+                    .ok_or_else(|| "Receipt block number does not fit in u64".to_string())
+            })
+            .transpose()
+    }
+
+    /// Whether `address` has contract code on `dest_chain` (via `eth_getCode`, cached)
+    pub async fn is_contract(&self, address: &str, dest_chain: &str) -> Result<bool, String> {
+        let key = (dest_chain.to_string(), address.to_lowercase());
+        if let Some(cached) = CODE_PRESENCE.with(|cache| cache.borrow().get(&key).copied()) {
+            return Ok(cached);
+        }
+
+        let result = self
+            .json_rpc_request_within(
+                dest_chain,
+                "eth_getCode",
+                serde_json::json!([address, "latest"]),
+                CODE_RESPONSE_BYTES,
+            )
+            .await?;
+        let code = result
+            .as_str()
+            .ok_or_else(|| format!("Unexpected eth_getCode result: {}", result))?;
+        let has_code = code_is_present(code);
+
+        CODE_PRESENCE.with(|cache| cache.borrow_mut().insert(key, has_code));
+        Ok(has_code)
+    }
+
+    /// Run a read-only `eth_call` against `to` and return the raw return data
+    pub async fn eth_call(
+        &self,
+        to: &str,
+        data: &[u8],
+        dest_chain: &str,
+    ) -> Result<Vec<u8>, String> {
+        let result = self
+            .json_rpc_request(
+                dest_chain,
+                "eth_call",
+                serde_json::json!([
+                    { "to": to, "data": format!("0x{}", hex::encode(data)) },
+                    "latest"
+                ]),
+            )
+            .await?;
+
+        let data = result
+            .as_str()
+            .and_then(|data| data.strip_prefix("0x"))
+            .ok_or_else(|| format!("Unexpected eth_call result: {}", result))?;
+        hex::decode(data).map_err(|e| format!("Invalid eth_call return data: {}", e))
+    }
+
+    /// Resolve `name` through the ENS `registry`: its resolver's `addr` record, checksummed
+    pub async fn resolve_ens_name(
+        &self,
+        name: &str,
+        registry: &str,
+        dest_chain: &str,
+    ) -> Result<String, String> {
+        resolve_ens_with(name, registry, |to, data| async move {
+            self.eth_call(&to, &data, dest_chain).await
+        })
+        .await
+    }
+
+    /// Confirm `token` implements the ERC-20 metadata calls, returning `(symbol, decimals)`
+    pub async fn probe_erc20(&self, token: &str, dest_chain: &str) -> Result<(String, u8), String> {
+        let decimals = self
+            .eth_call(token, &ethers_core::utils::id("decimals()"), dest_chain)
+            .await
+            .and_then(|data| decode_erc20_decimals(&data))
+            .map_err(|e| format!("Token {} does not implement decimals(): {}", token, e))?;
+        let symbol = self
+            .eth_call(token, &ethers_core::utils::id("symbol()"), dest_chain)
+            .await
+            .and_then(|data| decode_erc20_symbol(&data))
+            .map_err(|e| format!("Token {} does not implement symbol(): {}", token, e))?;
+
+        Ok((symbol, decimals))
+    }
+
+    /// ERC-20 `balanceOf(holder)` of `token`, in the token's base units
+    pub async fn fetch_erc20_balance(
+        &self,
+        token: &str,
+        holder: &str,
+        dest_chain: &str,
+    ) -> Result<U256, String> {
+        let holder =
+            H160::from_str(holder).map_err(|e| format!("Invalid holder address: {}", e))?;
+        let mut data = ethers_core::utils::id("balanceOf(address)").to_vec();
+        data.extend(ethabi::encode(&[Token::Address(holder)]));
+
+        let data = self.eth_call(token, &data, dest_chain).await?;
+        decode_erc20_balance(&data)
+    }
+
+    /// Pay `amount` base units of a configured ERC-20 token to `to`
+    pub async fn send_erc20(
+        &self,
+        symbol: &str,
+        to: String,
+        amount: U256,
+        dest_chain: String,
+        gas_overrides: Option<GasOverrides>,
+        nonce_override: Option<u64>,
+    ) -> Result<String, String> {
+        let token = get_token_config(&dest_chain, symbol)
+            .ok_or_else(|| format!("No token config for {} on chain {}", symbol, dest_chain))?;
+
+        let (_, decimals) = self.probe_erc20(&token.address, &dest_chain).await?;
+        if decimals != token.decimals {
+            return Err(format!(
+                "Token {} reports {} decimals but {} are configured",
+                token.address, decimals, token.decimals
+            ));
+        }
+
+        let vault_address = get_canister_ethereum_address(&dest_chain).await?;
+        let balance = self
+            .fetch_erc20_balance(&token.address, &vault_address, &dest_chain)
+            .await?;
+        check_token_balance(symbol, amount, balance)?;
+
+        let to = H160::from_str(&to).map_err(|e| format!("Invalid address format: {}", e))?;
+        let token_address =
+            H160::from_str(&token.address).map_err(|e| format!("Invalid token address: {}", e))?;
+        let mut data = ethers_core::utils::id("transfer(address,uint256)").to_vec();
+        data.extend(ethabi::encode(&[Token::Address(to), Token::Uint(amount)]));
+
+        let nonce = self.next_nonce(nonce_override, &dest_chain).await?;
+        let (_, max_fee_per_gas, max_priority_fee_per_gas) =
+            estimate_transaction_fees_for_chain(&dest_chain).await;
+        let (chain_id, _) = get_rpc_config(&dest_chain);
+        let gas_limit = apply_gas_limit_grace(
+            ERC20_TRANSFER_GAS_LIMIT,
+            get_chain_config(&dest_chain).gas_limit_grace(),
+        );
+        let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) = apply_gas_overrides(
+            (gas_limit, max_fee_per_gas, max_priority_fee_per_gas),
+            gas_overrides.as_ref(),
+        )?;
+
+        let tx = Eip1559TransactionRequest {
+            from: None,
+            to: Some(token_address.into()),
+            nonce: Some(U256::from(nonce)),
+            gas: Some(U256::from(gas_limit)),
+            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
+            max_priority_fee_per_gas: Some(U256::from(max_priority_fee_per_gas)),
+            value: Some(U256::zero()),
+            data: Some(Bytes::from(data)),
+            access_list: vec![].into(),
+            chain_id: Some(U64::from(chain_id)),
+        };
+
+        self.sign_and_submit(tx, &dest_chain).await
+    }
+}
+
+/// Blocks from `tx_block` up to and including `latest_block`; 0 if `tx_block` is newer
+pub fn confirmations(tx_block: u64, latest_block: u64) -> u64 {
+    if latest_block < tx_block {
+        0
+    } else {
+        latest_block - tx_block + 1
+    }
+}
+
+/// Whether a transaction included in `tx_block` has reached `config.finality_blocks`
+pub fn is_final(config: &ChainConfig, tx_block: u64, latest_block: u64) -> bool {
+    confirmations(tx_block, latest_block) >= config.finality_blocks
+}
+
+/// Confirmation depth of a transaction against its chain's finality threshold
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TxFinality {
+    /// `None` while the transaction has no receipt
+    pub block_number: Option<u64>,
+    pub confirmations: u64,
+    pub finality_blocks: u64,
+    pub is_final: bool,
+}
+
+// Phrases that, next to "cycles", mark a rejection for too few attached cycles
+const CYCLES_REJECTION_HINTS: [&str; 5] = [
+    "insufficient",
+    "not enough",
+    "too few",
+    "required",
+    "out of cycles",
+];
+
+/// Whether a rejected call was refused because too few cycles were attached
+pub fn is_cycles_rejection(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("cycles")
+        && CYCLES_REJECTION_HINTS
+            .iter()
+            .any(|hint| message.contains(hint))
+}
+
+/// Describe a rejected EVM RPC canister call; a cycles rejection becomes an actionable
+/// message naming the call whose budget needs raising, anything else is `context: error`
+pub fn rpc_call_error(
+    method: &str,
+    attached: u128,
+    context: &str,
+    (code, message): (RejectionCode, String),
+) -> String {
+    if is_cycles_rejection(&message) {
+        ic_cdk::println!(
+            "⛽ {} rejected for cycles: {} attached ({})",
+            method,
+            attached,
+            message
+        );
+        return format!(
+            "Insufficient cycles for outcall: increase cycles budget for {} ({} attached)",
+            method, attached
+        );
+    }
+    format!("{}: {:?}", context, (code, message))
+}
+
+/// The actionable message for an EVM RPC `TooFewCycles` error, `None` for any other error
+pub fn too_few_cycles_error(method: &str, error: &RpcError) -> Option<String> {
+    match error {
+        RpcError::ProviderError(ProviderError::TooFewCycles { expected, received }) => {
+            ic_cdk::println!(
+                "⛽ {} needs {} cycles, {} attached",
+                method,
+                expected,
+                received
+            );
+            Some(format!(
+                "Insufficient cycles for outcall: increase cycles budget for {} (required {}, attached {})",
+                method, expected, received
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Hash of a signed transaction given as (optionally 0x-prefixed) hex
+pub fn signed_tx_hash(raw_tx_hex: &str) -> Result<String, String> {
+    let bytes = hex::decode(raw_tx_hex.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid raw transaction hex: {}", e))?;
+    Ok(format!(
+        "0x{}",
+        hex::encode(ethers_core::utils::keccak256(bytes))
+    ))
+}
+
+/// Settle disagreeing `eth_sendRawTransaction` results. Every provider got the same signed
+/// bytes, so any one accepting the transaction under `expected_hash` means it was broadcast;
+/// otherwise the majority outcome is used, failing when there is none.
+pub fn resolve_inconsistent_send(
+    results: Vec<(RpcService, SendRawTransactionResult)>,
+    expected_hash: &str,
+) -> Result<SendRawTransactionResult, String> {
+    let accepted = results.iter().position(|(_, result)| {
+        matches!(
+            result,
+            SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(Some(hash)))
+                if hash.eq_ignore_ascii_case(expected_hash)
+        )
+    });
+    if let Some(index) = accepted {
+        ic_cdk::println!(
+            "Providers disagree; {:?} accepted {}",
+            results[index].0,
+            expected_hash
+        );
+        let mut results = results;
+        return Ok(results.swap_remove(index).1);
+    }
+
+    let total = results.len();
+    let outcomes: Vec<String> = results
+        .iter()
+        .map(|(_, result)| format!("{:?}", result))
+        .collect();
+    let majority = outcomes
+        .iter()
+        .position(|outcome| outcomes.iter().filter(|other| *other == outcome).count() * 2 > total);
+    if let Some(index) = majority {
+        ic_cdk::println!(
+            "Providers disagree; using majority outcome {}",
+            outcomes[index]
+        );
+        let mut results = results;
+        return Ok(results.swap_remove(index).1);
+    }
+
+    let breakdown = results
+        .iter()
+        .map(|(service, result)| format!("{:?} => {:?}", service, result))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(format!(
+        "Inconsistent send raw transaction results across {} providers: {}",
+        total, breakdown
+    ))
+}
+
+/// Human-readable reason from an `eth_call` JSON-RPC error: the decoded `Error(string)` /
+/// `Panic(uint256)` revert data when present, otherwise the error message
+pub fn revert_reason(error: &serde_json::Value) -> String {
+    let decoded = error
+        .get("data")
+        .and_then(|data| data.as_str())
+        .and_then(|data| hex::decode(data.trim_start_matches("0x")).ok())
+        .and_then(|data| decode_revert_data(&data));
+    if let Some(reason) = decoded {
+        return reason;
+    }
+    error
+        .get("message")
+        .and_then(|message| message.as_str())
+        .map(|message| message.to_string())
+        .unwrap_or_else(|| error.to_string())
+}
+
+/// Outcome of an `eth_call` simulation response: an error aborts the send with its reason
+pub fn simulation_result(response: &serde_json::Value) -> Result<(), String> {
+    match response.get("error") {
+        Some(error) => Err(format!(
+            "Simulation reverted, transaction not sent: {}",
+            revert_reason(error)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Decode ABI revert data: `Error(string)` yields the message, `Panic(uint256)` the code
+pub fn decode_revert_data(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, args) = data.split_at(4);
+    if selector == ethers_core::utils::id("Error(string)") {
+        let tokens = ethabi::decode(&[ParamType::String], args).ok()?;
+        match tokens.first()? {
+            Token::String(reason) => Some(reason.clone()),
+            _ => None,
+        }
+    } else if selector == ethers_core::utils::id("Panic(uint256)") {
+        let tokens = ethabi::decode(&[ParamType::Uint(256)], args).ok()?;
+        match tokens.first()? {
+            Token::Uint(code) => Some(format!("panic code {:#x}", code)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+// Gas limit for an ERC-20 `transfer`, well above typical token implementations
+const ERC20_TRANSFER_GAS_LIMIT: u128 = 100_000;
+
+/// Decode the return data of ERC-20 `decimals()` (a single uint8 word)
+pub fn decode_erc20_decimals(data: &[u8]) -> Result<u8, String> {
+    let tokens = ethabi::decode(&[ParamType::Uint(8)], data)
+        .map_err(|e| format!("Invalid decimals() return data: {}", e))?;
+    match tokens.first() {
+        Some(Token::Uint(decimals)) if *decimals <= U256::from(u8::MAX) => {
+            Ok(decimals.as_u32() as u8)
+        }
+        _ => Err("decimals() did not return a uint8".to_string()),
+    }
+}
+
+/// Decode the return data of ERC-20 `balanceOf(address)` (a single uint256 word)
+pub fn decode_erc20_balance(data: &[u8]) -> Result<U256, String> {
+    let tokens = ethabi::decode(&[ParamType::Uint(256)], data)
+        .map_err(|e| format!("Invalid balanceOf() return data: {}", e))?;
+    match tokens.first() {
+        Some(Token::Uint(balance)) => Ok(*balance),
+        _ => Err("balanceOf() did not return a uint256".to_string()),
+    }
+}
+
+/// Refuse an ERC-20 payout of `amount` base units that the vault's `balance` cannot cover
+pub fn check_token_balance(symbol: &str, amount: U256, balance: U256) -> Result<(), String> {
+    if amount > balance {
+        return Err(format!(
+            "Transfer of {} {} base units exceeds the vault balance of {}",
+            amount, symbol, balance
+        ));
+    }
+    Ok(())
+}
+
+/// Decode the return data of ERC-20 `symbol()`: an ABI string, or the bytes32 some older
+/// tokens return
+pub fn decode_erc20_symbol(data: &[u8]) -> Result<String, String> {
+    if let Ok(tokens) = ethabi::decode(&[ParamType::String], data) {
+        if let Some(Token::String(symbol)) = tokens.into_iter().next() {
+            return Ok(symbol);
+        }
+    }
+    if data.len() == 32 {
+        let end = data.iter().position(|&b| b == 0).unwrap_or(32);
+        return String::from_utf8(data[..end].to_vec())
+            .map_err(|e| format!("Invalid bytes32 symbol: {}", e));
+    }
+    Err("symbol() did not return a string".to_string())
+}
+
+/// Whether an `eth_getCode` result is non-empty contract code (EOAs return `"0x"`)
+pub fn code_is_present(code: &str) -> bool {
+    !code.trim_start_matches("0x").is_empty()
+}
+
+/// Gas limit of a native send, given whether the recipient has code: contracts get
+/// headroom for their receive logic, and `default` is used when the check failed
+pub fn native_gas_limit(is_contract: Result<bool, String>, default: u128) -> u128 {
+    match is_contract {
+        Ok(true) => CONTRACT_TRANSFER_GAS_LIMIT,
+        Ok(false) => NATIVE_TRANSFER_GAS_LIMIT,
+        Err(e) => {
+            ic_cdk::println!("eth_getCode failed ({}), using default gas limit", e);
+            default
+        }
+    }
+}
+
+/// `eth_getBalance` params for `address` as of `block_tag`
+pub fn balance_params(address: &str, block_tag: &BlockTag) -> serde_json::Value {
+    serde_json::json!([address, block_tag_param(block_tag)])
+}
+
+/// The JSON-RPC block parameter for `tag`: a tag name or a hex block number
+pub fn block_tag_param(tag: &BlockTag) -> String {
+    match tag {
+        BlockTag::Earliest => "earliest".to_string(),
+        BlockTag::Safe => "safe".to_string(),
+        BlockTag::Finalized => "finalized".to_string(),
+        BlockTag::Latest => "latest".to_string(),
+        BlockTag::Pending => "pending".to_string(),
+        BlockTag::Number(number) => format!("{:#x}", number.0),
+    }
+}
+
+/// Parse a JSON-RPC hex quantity such as `"0x1bc16d674ec80000"`
+pub fn parse_hex_quantity(quantity: &str) -> Result<U256, String> {
+    let digits = quantity
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("Hex quantity must start with 0x: {}", quantity))?;
+    if digits.is_empty() {
+        return Err("Empty hex quantity".to_string());
+    }
+    U256::from_str_radix(digits, 16)
+        .map_err(|e| format!("Invalid hex quantity {}: {}", quantity, e))
+}
+
+/// Convert a floating-point ETH amount to wei, refusing NaN, infinite, negative and
+/// out-of-range values instead of letting the cast saturate
+pub fn eth_to_wei_f64(eth_amount: f64) -> Result<U256, String> {
+    if !eth_amount.is_finite() {
+        return Err(format!("ETH amount must be finite, got {}", eth_amount));
+    }
+    if eth_amount < 0.0 {
+        return Err(format!(
+            "ETH amount must not be negative, got {}",
+            eth_amount
+        ));
+    }
+    let wei = eth_amount * 1e18;
+    // u128::MAX as f64 rounds up to 2^128, which itself does not fit
+    if wei >= u128::MAX as f64 {
+        return Err(format!("ETH amount {} is too large", eth_amount));
+    }
+    Ok(U256::from(wei as u128))
+}
+
+/// Reject send amounts that are NaN, infinite, zero or negative
+pub fn validate_send_amount(amount: f64) -> Result<(), String> {
+    if amount.is_finite() && amount > 0.0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Amount must be a positive, finite number of ETH, got {}",
+            amount
+        ))
+    }
+}
+
+/// Decimals of the native asset on every supported EVM chain
+pub const ETH_DECIMALS: u8 = 18;
+/// Symbol of the native asset on every supported EVM chain
+pub const NATIVE_SYMBOL: &str = "ETH";
+// Largest `decimals` for which 10^decimals still fits in a U256
+const MAX_UNIT_DECIMALS: u8 = 77;
+
+/// Exact decimal string of `amount` base units with `decimals` places, without trailing
+/// zeros: `1` wei at 18 decimals is `0.000000000000000001`, 10^18 wei is `1`
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = usize::from(decimals);
+    if decimals == 0 {
+        return digits;
+    }
+
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Inverse of `format_units`: parse a decimal string into base units, rejecting more
+/// fractional digits than `decimals` rather than rounding them away
+pub fn parse_units(amount: &str, decimals: u8) -> Result<U256, String> {
+    if decimals > MAX_UNIT_DECIMALS {
+        return Err(format!(
+            "At most {} decimals are supported",
+            MAX_UNIT_DECIMALS
+        ));
+    }
+    let amount = amount.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(format!("Invalid amount: {:?}", amount));
+    }
+    if !whole
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("Invalid amount: {:?}", amount));
+    }
+    if fraction.len() > usize::from(decimals) {
+        return Err(format!(
+            "Amount {} has more than {} decimal places",
+            amount, decimals
+        ));
+    }
+
+    let digits = format!(
+        "{}{:0<width$}",
+        whole,
+        fraction,
+        width = usize::from(decimals)
+    );
+    U256::from_dec_str(&digits).map_err(|e| format!("Amount {} is out of range: {}", amount, e))
+}
+
+/// Format a wei string as a decimal amount with `decimals` places (18 for ETH)
+#[ic_cdk::query]
+fn format_wei_to_eth(wei: String, decimals: u8) -> Result<String, String> {
+    if decimals > MAX_UNIT_DECIMALS {
+        return Err(format!(
+            "At most {} decimals are supported",
+            MAX_UNIT_DECIMALS
+        ));
+    }
+    let amount =
+        U256::from_dec_str(wei.trim()).map_err(|e| format!("Invalid wei amount {}: {}", wei, e))?;
+    Ok(format_units(amount, decimals))
+}
+
+/// Parse a decimal amount with `decimals` places (18 for ETH) into a wei string
+#[ic_cdk::query]
+fn parse_eth_to_wei(amount: String, decimals: u8) -> Result<String, String> {
+    parse_units(&amount, decimals).map(|wei| wei.to_string())
+}
+
+// Derivation path of a named vault; the "vault" prefix keeps it disjoint from the
+// single-component per-caller paths
+fn vault_derivation_path(label: &str) -> Vec<Vec<u8>> {
+    if label == CANISTER_VAULT_LABEL {
+        vec![]
+    } else {
+        vec![b"vault".to_vec(), label.as_bytes().to_vec()]
+    }
+}
+
+#[update]
+pub fn add_vault_label(label: String) -> Result<(), String> {
+    require_controller()?;
+
+    if label.is_empty() || label == CANISTER_VAULT_LABEL {
+        return Err(format!("Invalid vault label: {:?}", label));
+    }
+
+    VAULT_LABELS.with(|labels| {
+        let mut labels = labels.borrow_mut();
+        if labels.contains(&label) {
+            return Ok(());
+        }
+        // One slot is always taken by the canister's default vault
+        if labels.len() + 1 >= MAX_VAULTS {
+            return Err(format!("At most {} vaults are supported", MAX_VAULTS));
+        }
+        labels.push(label);
+        Ok(())
+    })
+}
+
+/// Label and derivation path of every vault `get_vault_balances` reports: the canister's
+/// default address, the named vaults (at most `MAX_VAULTS` together), then the addresses
+/// of up to `MAX_CALLER_VAULTS` callers in the order given
+pub fn vault_entries(labels: &[String], callers: &[Principal]) -> Vec<(String, Vec<Vec<u8>>)> {
+    let named = std::iter::once(CANISTER_VAULT_LABEL.to_string())
+        .chain(labels.iter().cloned())
+        .take(MAX_VAULTS)
+        .map(|label| {
+            let path = vault_derivation_path(&label);
+            (label, path)
+        });
+    let per_caller = callers.iter().take(MAX_CALLER_VAULTS).map(|caller| {
+        (
+            format!("{}{}", CALLER_VAULT_PREFIX, caller),
+            vec![caller.as_slice().to_vec()],
+        )
+    });
+    named.chain(per_caller).collect()
+}
+
+// Callers with a derived address, most recently active first
+fn recent_callers() -> Vec<Principal> {
+    let mut callers: Vec<(Principal, u64)> = CALLER_USAGE.with(|usage| {
+        usage
+            .borrow()
+            .iter()
+            .map(|(caller, usage)| (*caller, usage.last_transfer))
+            .collect()
+    });
+    callers.sort_by_key(|(_, last_transfer)| std::cmp::Reverse(*last_transfer));
+    callers.into_iter().map(|(caller, _)| caller).collect()
+}
+
+/// List every known vault with its derived address and native balance on `dest_chain`,
+/// as (label, address, balance in wei).
+///
+/// The canister's default address is always reported first, followed by the named vaults
+/// and the per-caller addresses (labelled `caller:<principal>`). A vault whose address or
+/// balance cannot be fetched reports `error: <reason>` as its balance, and an unknown
+/// chain or missing chain service yields a single `error` row.
+#[update]
+pub async fn get_vault_balances(dest_chain: String) -> Vec<(String, String, String)> {
+    use crate::evm_indexer::chain_service;
+
+    let (dest_chain, service) = match normalize_chain(&dest_chain)
+        .and_then(|chain| chain_service().map(|service| (chain, service)))
+    {
+        Ok(chain_and_service) => chain_and_service,
+        Err(e) => return vec![vault_error_row("error".to_string(), String::new(), e)],
+    };
+
+    let entries = vault_entries(&vault_labels_snapshot(), &recent_callers());
+    let mut balances = Vec::with_capacity(entries.len());
+    for (label, derivation_path) in entries {
+        let address = match ecdsa_public_key(EcdsaPublicKeyArgument {
+            key_id: key_id(&dest_chain),
+            derivation_path,
+            ..Default::default()
+        })
+        .await
+        {
+            Ok((response,)) => pubkey_bytes_to_address(&response.public_key),
+            Err(e) => Err(format!("Failed to get public key: {:?}", e)),
+        };
+        let address = match address {
+            Ok(address) => address,
+            Err(e) => {
+                balances.push(vault_error_row(label, String::new(), e));
+                continue;
+            }
+        };
+        match service
+            .fetch_balance(&address, &dest_chain, BlockTag::Latest)
+            .await
+        {
+            Ok(balance) => balances.push((label, address, balance.to_string())),
+            Err(e) => balances.push(vault_error_row(label, address, e)),
+        }
+    }
+
+    balances
+}
+
+// A `get_vault_balances` row whose balance could not be read
+fn vault_error_row(label: String, address: String, error: String) -> (String, String, String) {
+    ic_cdk::println!("⚠️ Cannot read vault {} balance: {}", label, error);
+    (label, address, format!("error: {}", error))
+}
+
+/// The canister's native balance (in wei) on every supported chain, ordered by chain id.
+/// A chain whose address or balance cannot be fetched reports `error: <reason>` instead.
+#[update]
+pub async fn get_vault_balances_all_chains() -> Vec<(String, String)> {
+    use crate::evm_indexer::chain_service;
+
+    let service = chain_service();
+    let chains = get_supported_chains()
+        .iter()
+        .map(|chain| chain.chain_id.to_string())
+        .collect();
+
+    balances_by_chain(chains, |dest_chain| {
+        let service = &service;
+        async move {
+            let service = service.as_ref().map_err(Clone::clone)?;
+            // Public keys are cached per key name, so chains sharing a key derive it once
+            let address = get_canister_ethereum_address(&dest_chain).await?;
+            service
+                .fetch_balance(&address, &dest_chain, BlockTag::Latest)
+                .await
+                .map(|balance| balance.to_string())
+        }
+    })
+    .await
+}
+
+/// `fetch_balance` for each chain in turn, a failing chain's entry holding `error: <reason>`
+pub async fn balances_by_chain<F, Fut>(
+    chains: Vec<String>,
+    mut fetch_balance: F,
+) -> Vec<(String, String)>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let mut balances = Vec::new();
+    for dest_chain in chains {
+        let balance = fetch_balance(dest_chain.clone())
+            .await
+            .unwrap_or_else(|e| format!("error: {}", e));
+        balances.push((dest_chain, balance));
+    }
+    balances
+}
+
+#[update]
+pub async fn generate_canister_key_pair_evm(dest_chain: String) -> Result<String, String> {
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    let request = EcdsaPublicKeyArgument {
+        key_id: key_id(&dest_chain),
+        derivation_path: vec![],
+        ..Default::default()
+    };
+
+    let (response,) = ecdsa_public_key(request)
+        .await
+        .map_err(|e| format!("ecdsa_public_key failed {:?}", e))?;
+
+    ic_cdk::println!("ECDSA public key response: {:?}", response);
+
+    let public_key_hex = hex::encode(&response.public_key);
+
+    ic_cdk::println!("Derived public key hex: {}", public_key_hex);
+
+    let ethereum_address = pubkey_bytes_to_address(&response.public_key)?;
+
+    Ok(ethereum_address)
+}
+
+
+#[update]
+pub async fn generate_key_pair_evm(dest_chain: String) -> Result<String, String> {
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    let request = EcdsaPublicKeyArgument {
+        key_id: key_id(&dest_chain),
+        derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
+        ..Default::default()
+    };
+
+    let (response,) = ecdsa_public_key(request)
+        .await
+        .map_err(|e| format!("ecdsa_public_key failed {:?}", e))?;
+
+    ic_cdk::println!("ECDSA public key response: {:?}", response);
+
+    let public_key_hex = hex::encode(&response.public_key);
+
+    ic_cdk::println!("Derived public key hex: {}", public_key_hex);
+
+    let ethereum_address = pubkey_bytes_to_address(&response.public_key)?;
+
+    Ok(ethereum_address)
+}
+
+/// Derive Ethereum address from SEC1 secp256k1 public key bytes: compressed (33 bytes,
+/// 0x02/0x03 prefix) or uncompressed (65 bytes, 0x04 prefix). Anything else is an error.
+pub fn pubkey_bytes_to_address(pubkey_bytes: &[u8]) -> Result<String, String> {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    use sha3::Keccak256;
+
+    match (pubkey_bytes.len(), pubkey_bytes.first()) {
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => {}
+        (0, _) => return Err("ECDSA public key is empty".to_string()),
+        (len, prefix) => {
+            return Err(format!(
+                "Malformed ECDSA public key: {} bytes with prefix {:02x?}",
+                len, prefix
+            ))
+        }
+    }
+    let key = PublicKey::from_sec1_bytes(pubkey_bytes)
+        .map_err(|e| format!("Invalid SEC1 public key: {}", e))?;
+    let point = key.to_encoded_point(false);
+    let point_bytes = point.as_bytes();
+
+    let hash = Keccak256::digest(&point_bytes[1..]);
+
+    let address = Address::from_slice(&hash[12..32]);
+    Ok(ethers_core::utils::to_checksum(&address.into(), None))
+}
+
+/// Fields of a signed EIP-1559 transaction, as recovered by `decode_signed_tx`
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DecodedTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub to: Option<String>,
+    pub value_wei: String,
+    pub gas: u64,
+    pub max_fee: u128,
+    pub max_priority_fee: u128,
+    pub data: String,
+    /// Sender recovered from the signature
+    pub recovered_from: String,
+}
+
+/// Decode a raw signed EIP-1559 transaction (`0x02 || rlp(...)`) and recover its sender
+#[ic_cdk::query]
+pub fn decode_signed_tx(raw_tx_hex: String) -> Result<DecodedTx, String> {
+    let raw = hex::decode(raw_tx_hex.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid transaction hex: {}", e))?;
+    let payload = match raw.split_first() {
+        Some((&EIP1559_TX_ID, payload)) => payload,
+        _ => return Err("Not an EIP-1559 (0x02) transaction".to_string()),
+    };
+
+    let rlp = ethers_core::utils::rlp::Rlp::new(payload);
+    let (tx, _) = Eip1559TransactionRequest::decode_signed_rlp(&rlp)
+        .map_err(|e| format!("Failed to decode signed transaction: {}", e))?;
+
+    let quantity = |value: Option<U256>| value.unwrap_or_default();
+    Ok(DecodedTx {
+        chain_id: tx.chain_id.map_or(0, |chain_id| chain_id.as_u64()),
+        nonce: quantity(tx.nonce).as_u64(),
+        to: tx
+            .to
+            .as_ref()
+            .and_then(|to| to.as_address())
+            .map(|to| ethers_core::utils::to_checksum(to, None)),
+        value_wei: quantity(tx.value).to_string(),
+        gas: quantity(tx.gas).as_u64(),
+        max_fee: quantity(tx.max_fee_per_gas).as_u128(),
+        max_priority_fee: quantity(tx.max_priority_fee_per_gas).as_u128(),
+        data: format!("0x{}", hex::encode(tx.data.unwrap_or_default())),
+        recovered_from: tx
+            .from
+            .map(|from| ethers_core::utils::to_checksum(&from, None))
+            .unwrap_or_default(),
+    })
+}
+
+// Fee multiplier applied to the chain's normal fees when replacing a stuck transaction
+const CLEAR_NONCE_FEE_MULTIPLIER: u128 = 3;
+
+/// Build the 0-value self-send used to replace a stuck transaction at `nonce`
+pub fn build_clear_nonce_tx(
+    canister_address: &str,
+    nonce: u64,
+    chain_id: u64,
+    dest_chain: &str,
+) -> Result<Eip1559TransactionRequest, String> {
+    let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) =
+        transfer_fees_for_chain(dest_chain);
+    let to =
+        H160::from_str(canister_address).map_err(|e| format!("Invalid canister address: {}", e))?;
+
+    Ok(Eip1559TransactionRequest {
+        from: None,
+        to: Some(to.into()),
+        nonce: Some(U256::from(nonce)),
+        gas: Some(U256::from(gas_limit)),
+        max_fee_per_gas: Some(U256::from(max_fee_per_gas * CLEAR_NONCE_FEE_MULTIPLIER)),
+        max_priority_fee_per_gas: Some(U256::from(
+            max_priority_fee_per_gas * CLEAR_NONCE_FEE_MULTIPLIER,
+        )),
+        value: Some(U256::zero()),
+        data: None,
+        access_list: vec![].into(),
+        chain_id: Some(U64::from(chain_id)),
+    })
+}
+
+/// Per-send replacements for estimated gas values; unset fields keep the estimate
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct GasOverrides {
+    pub gas_limit: Option<u128>,
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+/// Merge `overrides` into the estimated `(gas_limit, max_fee, priority_fee)`, refusing a
+/// result below the transfer gas floor or with a priority fee above the max fee
+pub fn apply_gas_overrides(
+    (gas_limit, max_fee_per_gas, max_priority_fee_per_gas): (u128, u128, u128),
+    overrides: Option<&GasOverrides>,
+) -> Result<(u128, u128, u128), String> {
+    let Some(overrides) = overrides else {
+        return Ok((gas_limit, max_fee_per_gas, max_priority_fee_per_gas));
+    };
+    let gas_limit = overrides.gas_limit.unwrap_or(gas_limit);
+    let max_fee_per_gas = overrides.max_fee_per_gas.unwrap_or(max_fee_per_gas);
+    let max_priority_fee_per_gas = overrides
+        .max_priority_fee_per_gas
+        .unwrap_or(max_priority_fee_per_gas);
+
+    if gas_limit < NATIVE_TRANSFER_GAS_LIMIT {
+        return Err(format!(
+            "Gas limit {} is below the {} needed for a transfer",
+            gas_limit, NATIVE_TRANSFER_GAS_LIMIT
+        ));
+    }
+    if max_priority_fee_per_gas > max_fee_per_gas {
+        return Err(format!(
+            "Priority fee {} exceeds max fee {}",
+            max_priority_fee_per_gas, max_fee_per_gas
+        ));
+    }
+    ic_cdk::println!(
+        "Gas overrides applied - limit: {}, max_fee: {}, priority_fee: {}",
+        gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas
+    );
+    Ok((gas_limit, max_fee_per_gas, max_priority_fee_per_gas))
+}
+
+/// The chain's static fees, with the max fee raised to cover the live base fee when it
+/// can be read
+pub async fn estimate_transaction_fees_for_chain(dest_chain: &str) -> (u128, u128, u128) {
+    let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) =
+        transfer_fees_for_chain(dest_chain);
+    match base_fee(dest_chain).await {
+        Ok(base_fee) => (
+            gas_limit,
+            dynamic_max_fee(base_fee, max_fee_per_gas, max_priority_fee_per_gas),
+            max_priority_fee_per_gas,
+        ),
+        Err(e) => {
+            ic_cdk::println!("⚠️ Using static fees for chain {}: {}", dest_chain, e);
+            (gas_limit, max_fee_per_gas, max_priority_fee_per_gas)
+        }
+    }
+}
+
+/// Max fee that still covers the base fee after it doubles, plus the priority fee; never
+/// below the chain's static `max_fee_per_gas`
+pub fn dynamic_max_fee(base_fee: U256, max_fee_per_gas: u128, priority_fee: u128) -> u128 {
+    let dynamic = base_fee
+        .saturating_mul(U256::from(2))
+        .saturating_add(U256::from(priority_fee));
+    if dynamic > U256::from(u128::MAX) {
+        return u128::MAX;
+    }
+    dynamic.as_u128().max(max_fee_per_gas)
+}
+
+/// The next block's base fee from an `eth_feeHistory` result: the last `baseFeePerGas`
+/// entry, which follows the requested blocks
+pub fn fee_history_base_fee(history: &FeeHistory) -> Result<U256, String> {
+    let fee = history
+        .baseFeePerGas
+        .last()
+        .ok_or("Fee history has no baseFeePerGas (pre-London chain?)")?;
+    U256::from_dec_str(&fee.0.to_string()).map_err(|e| format!("Invalid base fee: {}", e))
+}
+
+// How long a fetched base fee is reused: about one Ethereum block
+const BASE_FEE_TTL_NANOS: u64 = 12_000_000_000;
+
+thread_local! {
+    // Canonical chain id -> (base fee in wei, fetched at in nanoseconds)
+    static BASE_FEES: RefCell<HashMap<String, (U256, u64)>> = RefCell::new(HashMap::new());
+}
+
+/// Latest base fee on `dest_chain`, served from a short-lived cache
+pub async fn base_fee(dest_chain: &str) -> Result<U256, String> {
+    let now = ic_cdk::api::time();
+    let cached = BASE_FEES.with(|fees| fees.borrow().get(dest_chain).copied());
+    if let Some((fee, fetched_at)) = cached {
+        if now.saturating_sub(fetched_at) < BASE_FEE_TTL_NANOS {
+            return Ok(fee);
+        }
+    }
+
+    let fee = crate::evm_indexer::chain_service()?
+        .fetch_base_fee(dest_chain)
+        .await?;
+    BASE_FEES.with(|fees| fees.borrow_mut().insert(dest_chain.to_string(), (fee, now)));
+    Ok(fee)
+}
+
+/// Current base fee per gas on `dest_chain`, in wei
+#[update]
+async fn get_base_fee(dest_chain: String) -> Result<Nat, String> {
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    let fee = base_fee(&dest_chain).await?;
+    Nat::from_str(&fee.to_string()).map_err(|e| format!("Invalid base fee: {}", e))
+}
+
+fn transfer_fees_for_chain(dest_chain: &str) -> (u128, u128, u128) {
+    match dest_chain {
+        "17000" => {
+            // Holesky Testnet - Lower fees for testnet
+            const GAS_LIMIT: u128 = 21_000; // Standard ETH transfer
+            const MAX_FEE_PER_GAS: u128 = 20_000_000_000; // 20 Gwei
+            const MAX_PRIORITY_FEE_PER_GAS: u128 = 2_000_000_000; // 2 Gwei
+            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
+        },
+        "8453" => {
+            // Base Mainnet - Optimized fees
+            const GAS_LIMIT: u128 = 21_000; // Standard ETH transfer
+            const MAX_FEE_PER_GAS: u128 = 1_000_000_000; // 1 Gwei (Base is cheaper)
+            const MAX_PRIORITY_FEE_PER_GAS: u128 = 1_000_000_000; // 1 Gwei
+            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
+        },
+        _ => {
+            // Default fallback
+            const GAS_LIMIT: u128 = 21_000;
+            const MAX_FEE_PER_GAS: u128 = 20_000_000_000; // 20 Gwei
+            const MAX_PRIORITY_FEE_PER_GAS: u128 = 2_000_000_000; // 2 Gwei
+            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
+        }
+    }
+}
+
+// Upper bound for set_sign_attempts; each attempt costs signing cycles
+const MAX_SIGN_ATTEMPTS: u32 = 5;
+// Pause between sign_with_ecdsa attempts
+const SIGN_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+thread_local! {
+    // Attempts made at sign_with_ecdsa when it fails with a transient error
+    static SIGN_ATTEMPTS: RefCell<u32> = const { RefCell::new(2) };
+}
+
+pub fn sign_attempts() -> u32 {
+    SIGN_ATTEMPTS.with(|attempts| *attempts.borrow())
+}
+
+pub fn restore_sign_attempts(attempts: u32) {
+    SIGN_ATTEMPTS.with(|current| *current.borrow_mut() = attempts);
+}
+
+#[update]
+pub fn set_sign_attempts(attempts: u32) -> Result<(), String> {
+    require_controller()?;
+
+    if !(1..=MAX_SIGN_ATTEMPTS).contains(&attempts) {
+        return Err(format!(
+            "Sign attempts must be between 1 and {}",
+            MAX_SIGN_ATTEMPTS
+        ));
+    }
+    restore_sign_attempts(attempts);
+    Ok(())
+}
+
+// Upper bound for set_nonce_fetch_retries
+const MAX_NONCE_FETCH_RETRIES: u32 = 5;
+// Pause before refetching a transaction count the providers disagreed on
+const NONCE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+thread_local! {
+    // Refetches of an inconsistent transaction count before taking the highest one
+    static NONCE_FETCH_RETRIES: RefCell<u32> = const { RefCell::new(2) };
+}
+
+pub fn nonce_fetch_retries() -> u32 {
+    NONCE_FETCH_RETRIES.with(|retries| *retries.borrow())
+}
+
+pub fn restore_nonce_fetch_retries(retries: u32) {
+    NONCE_FETCH_RETRIES.with(|current| *current.borrow_mut() = retries);
+}
+
+#[update]
+pub fn set_nonce_fetch_retries(retries: u32) -> Result<(), String> {
+    require_controller()?;
+
+    if retries > MAX_NONCE_FETCH_RETRIES {
+        return Err(format!(
+            "Nonce fetch retries must be at most {}",
+            MAX_NONCE_FETCH_RETRIES
+        ));
+    }
+    restore_nonce_fetch_retries(retries);
+    Ok(())
+}
+
+/// Transaction count for `args` from `request`. Providers disagreeing is usually a block
+/// still propagating, so the count is refetched up to `retries` times, waiting `delay` (if
+/// non-zero) in between, before settling on the highest count any provider reported.
+pub async fn settle_transaction_count<F, Fut>(
+    args: &GetTransactionCountArgs,
+    retries: u32,
+    delay: Duration,
+    mut request: F,
+) -> Result<Nat, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<MultiGetTransactionCountResult, String>>,
+{
+    let mut retries_left = retries;
+    loop {
+        match request().await? {
+            MultiGetTransactionCountResult::Consistent(result) => {
+                return transaction_count_result(result, args)
+            }
+            MultiGetTransactionCountResult::Inconsistent(results) if retries_left > 0 => {
+                ic_cdk::println!(
+                    "⚠️ Inconsistent transaction counts ({:?}), refetching",
+                    results
+                );
+                retries_left -= 1;
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+            }
+            MultiGetTransactionCountResult::Inconsistent(results) => {
+                return max_transaction_count(results, args)
+            }
+        }
+    }
+}
+
+// The count from a consistent eth_getTransactionCount result
+fn transaction_count_result(
+    result: GetTransactionCountResult,
+    args: &GetTransactionCountArgs,
+) -> Result<Nat, String> {
+    match result {
+        GetTransactionCountResult::Ok(count) => Ok(count),
+        GetTransactionCountResult::Err(error) => {
+            if let Some(message) = too_few_cycles_error("eth_getTransactionCount", &error) {
+                return Err(message);
+            }
+            Err(format!(
+                "failed to get transaction count for {:?}, error: {:?}",
+                args, error
+            ))
+        }
+    }
+}
+
+/// Highest transaction count among disagreeing providers. A lagging provider reports a
+/// nonce that may already be used; the highest one never is.
+pub fn max_transaction_count(
+    results: Vec<(RpcService, GetTransactionCountResult)>,
+    args: &GetTransactionCountArgs,
+) -> Result<Nat, String> {
+    let highest = results
+        .iter()
+        .filter_map(|(_, result)| match result {
+            GetTransactionCountResult::Ok(count) => Some(count.clone()),
+            GetTransactionCountResult::Err(_) => None,
+        })
+        .max();
+    highest.ok_or_else(|| {
+        format!(
+            "inconsistent results when retrieving transaction count for {:?}. Received results: {:?}",
+            args, results
+        )
+    })
+}
+
+/// Whether a rejected signing call may succeed if repeated: only transient system errors
+/// (e.g. a busy threshold ECDSA subnet), never rejections caused by the request itself
+pub fn is_retryable_sign_error(code: RejectionCode) -> bool {
+    code == RejectionCode::SysTransient
+}
+
+/// Run `sign` up to `attempts` times, waiting `delay` (if non-zero) between attempts, as
+/// long as it fails with a retryable rejection
+pub async fn retry_signing<T, F, Fut>(
+    attempts: u32,
+    delay: Duration,
+    mut sign: F,
+) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = CallResult<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match sign().await {
+            Ok(signed) => return Ok(signed),
+            Err((code, message)) if attempt < attempts && is_retryable_sign_error(code) => {
+                ic_cdk::println!(
+                    "⚠️ Signing attempt {} failed ({:?}: {}), retrying",
+                    attempt,
+                    code,
+                    message
+                );
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+                attempt += 1;
+            }
+            Err(e) => return Err(format!("Failed to generate signature {:?}", e)),
+        }
+    }
+}
+
+// Resolve after `delay`, using a one-shot timer to wake the awaiting task
+async fn sleep(delay: Duration) {
+    let state: Rc<RefCell<(bool, Option<Waker>)>> = Rc::new(RefCell::new((false, None)));
+    let timer_state = state.clone();
+    ic_cdk_timers::set_timer(delay, move || {
+        let mut state = timer_state.borrow_mut();
+        state.0 = true;
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    });
+    std::future::poll_fn(move |cx| {
+        let mut state = state.borrow_mut();
+        if state.0 {
+            Poll::Ready(())
+        } else {
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+// Threshold ECDSA signatures are r || s, 32 bytes each
+const ECDSA_SIGNATURE_LEN: usize = 64;
+
+/// Reject a signature that cannot be split into r and s, instead of trapping on the slice
+pub fn check_signature_len(signature: &[u8]) -> Result<(), String> {
+    if signature.len() != ECDSA_SIGNATURE_LEN {
+        return Err(format!(
+            "Unexpected ECDSA signature length: {} bytes (expected {})",
+            signature.len(),
+            ECDSA_SIGNATURE_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `sig` over `tx_hash` recovers to `expected_address` (checksummed), so a
+/// wrong parity or key is caught before the transaction is broadcast
+pub fn verify_signer(
+    tx_hash: &[u8; 32],
+    sig: &ethers_core::types::Signature,
+    expected_address: &str,
+) -> Result<(), String> {
+    let recovered = sig
+        .recover(ethers_core::types::H256::from(*tx_hash))
+        .map_err(|e| format!("Could not recover the transaction signer: {}", e))?;
+    let recovered = ethers_core::utils::to_checksum(&recovered, None);
+    if recovered != expected_address {
+        return Err(format!(
+            "Signature recovers to {}, expected {}; not broadcasting",
+            recovered, expected_address
+        ));
+    }
+    Ok(())
+}
+
+/// Recover the parity bit of `sig` by matching the recovered key against `pubkey`
+fn y_parity(prehash: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<u64, String> {
+    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+    let orig_key = VerifyingKey::from_sec1_bytes(pubkey)
+        .map_err(|e| format!("Failed to parse the ECDSA public key: {}", e))?;
+    let signature = Signature::try_from(sig)
+        .map_err(|e| format!("Failed to parse the ECDSA signature: {}", e))?;
+    for parity in [0u8, 1] {
+        let recid = RecoveryId::try_from(parity)
+            .map_err(|e| format!("Invalid recovery id {}: {}", parity, e))?;
+        if let Ok(recovered_key) = VerifyingKey::recover_from_prehash(prehash, &signature, recid) {
+            if recovered_key == orig_key {
+                return Ok(parity as u64);
+            }
+        }
+    }
+
+    Err(format!(
+        "Failed to recover the parity bit from a signature; sig: {}, pubkey: {}",
+        hex::encode(sig),
+        hex::encode(pubkey)
+    ))
+}
+
+const NETWORK: &str = "mainnet";
+pub fn get_network_config() -> (&'static str, &'static str) {
+    match NETWORK {
+        "local" => (
+            "0xDa824f554C42ecd28a74A037c70FA0b5bf447bB0", // address_local
+            "dfx_test_key",                               // ecdsa_key_local
+        ),
+        "mainnet" => (
+            "0x4f159ACaC4105822E3201674FD2323320Bb9dd38", // address_main
+            "test_key_1",                                 // ecdsa_key_main
+        ),
+        _ => panic!("Unknown network!"),
+    }
+}
+
+thread_local! {
+    // Canister public key per ECDSA key name. A key's public key never changes, so it is
+    // derived once (see warm_up) and reused by every later send.
+    static CANISTER_PUBLIC_KEYS: RefCell<HashMap<String, EcdsaPublicKeyResponse>> =
+        RefCell::new(HashMap::new());
+}
+
+pub async fn get_ecdsa_public_key(dest_chain: &str) -> Result<EcdsaPublicKeyResponse, String> {
+    let key_id = key_id(dest_chain);
+    let cached = CANISTER_PUBLIC_KEYS.with(|keys| keys.borrow().get(&key_id.name).cloned());
+    if let Some(response) = cached {
+        return Ok(response);
+    }
+
+    let res = ecdsa_public_key(EcdsaPublicKeyArgument {
+        key_id: key_id.clone(),
+        ..Default::default()
+    })
+    .await
+    .map_err(|e| format!("Failed to get public key: {:?}", e))?;
+
+    CANISTER_PUBLIC_KEYS.with(|keys| keys.borrow_mut().insert(key_id.name, res.0.clone()));
+    Ok(res.0)
+}
+
+// Derive (and cache) the canister key and address of every supported chain
+async fn warm_up_canister_keys() -> Result<Vec<(u64, String)>, String> {
+    let mut addresses = Vec::new();
+    for chain in get_supported_chains() {
+        let address = get_canister_ethereum_address(&chain.chain_id.to_string()).await?;
+        addresses.push((chain.chain_id, address));
+    }
+    Ok(addresses)
+}
+
+/// Derive the canister keys right after install or upgrade, so the first send does not pay
+/// for it. `init` cannot await the management canister, so this runs from a zero-delay
+/// timer; if it fails (e.g. the key is not yet available), call `warm_up` once deployed.
+pub fn schedule_warm_up() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        ic_cdk::spawn(async {
+            match warm_up_canister_keys().await {
+                Ok(addresses) => ic_cdk::println!("🔑 Canister keys warmed up: {:?}", addresses),
+                Err(e) => ic_cdk::println!("⚠️ Key warm-up failed, call warm_up: {}", e),
+            }
+        })
+    });
+}
+
+/// Derive and cache the canister key and address of every supported chain, returning
+/// `(chain_id, address)` pairs
+#[update]
+pub async fn warm_up() -> Result<Vec<(u64, String)>, String> {
+    require_controller()?;
+
+    warm_up_canister_keys().await
+}
+
+pub async fn get_caller_ecdsa_public_key(
+    dest_chain: &str,
+) -> Result<EcdsaPublicKeyResponse, String> {
+    let res = ecdsa_public_key(EcdsaPublicKeyArgument {
+        key_id: key_id(dest_chain),
+        derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
+        ..Default::default()
+    })
+    .await
+    .map_err(|e| format!("Failed to get caller public key: {:?}", e))?;
+
+    Ok(res.0)
+}
+
+pub async fn get_caller_ethereum_address(dest_chain: &str) -> Result<String, String> {
+    let public_key_response = get_caller_ecdsa_public_key(dest_chain).await?;
+    let ethereum_address = pubkey_bytes_to_address(&public_key_response.public_key)?;
+    Ok(ethereum_address)
+}
+
+/// Derive the canister's own Ethereum address for the key configured on `dest_chain`
+pub async fn get_canister_ethereum_address(dest_chain: &str) -> Result<String, String> {
+    let public_key_response = get_ecdsa_public_key(dest_chain).await?;
+    let ethereum_address = pubkey_bytes_to_address(&public_key_response.public_key)?;
+    Ok(ethereum_address)
+}
+
+/// Canister address derived for one chain from that chain's configured ECDSA key
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ChainAddressCheck {
+    pub chain_id: u64,
+    pub ecdsa_key_name: String,
+    pub derived_address: String,
+    pub matches: bool,
+}
+
+/// Result of comparing derived canister addresses with the address expected for the network
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AddressCheck {
+    pub expected_address: String,
+    pub chains: Vec<ChainAddressCheck>,
+    /// Set when any chain derives a different address than expected
+    pub warning: Option<String>,
+}
+
+/// Compare each chain's `(chain_id, key name, derived address)` with `expected_address`,
+/// case-insensitively so checksummed and lower-case forms agree
+pub fn check_derived_addresses(
+    expected_address: &str,
+    derived: Vec<(u64, String, String)>,
+) -> AddressCheck {
+    let chains: Vec<ChainAddressCheck> = derived
+        .into_iter()
+        .map(
+            |(chain_id, ecdsa_key_name, derived_address)| ChainAddressCheck {
+                matches: derived_address.eq_ignore_ascii_case(expected_address),
+                chain_id,
+                ecdsa_key_name,
+                derived_address,
+            },
+        )
+        .collect();
+
+    let mismatched: Vec<String> = chains
+        .iter()
+        .filter(|check| !check.matches)
+        .map(|check| {
+            format!(
+                "chain {} (key {}) derives {}",
+                check.chain_id, check.ecdsa_key_name, check.derived_address
+            )
+        })
+        .collect();
+    let warning = (!mismatched.is_empty()).then(|| {
+        format!(
+            "Expected canister address {} but {}; funds at the expected address are not reachable with the current key",
+            expected_address,
+            mismatched.join(", ")
+        )
+    });
+
+    AddressCheck {
+        expected_address: expected_address.to_string(),
+        chains,
+        warning,
+    }
+}
+
+/// Re-derive the canister address for every supported chain from its active ECDSA key and
+/// compare it with the network's expected address. Run after changing key configuration.
+#[ic_cdk::update]
+pub async fn verify_address_matches_key() -> Result<AddressCheck, String> {
+    let (expected_address, _) = get_network_config();
+
+    let mut derived = Vec::new();
+    for chain in get_supported_chains() {
+        let dest_chain = chain.chain_id.to_string();
+        let address = get_canister_ethereum_address(&dest_chain).await?;
+        derived.push((
+            chain.chain_id,
+            get_chain_config(&dest_chain).ecdsa_key_name,
+            address,
+        ));
+    }
 
-        ic_cdk::println!("🔄 transfer_eth - dest_chain: {}", dest_chain);
-        ic_cdk::println!("🔄 transfer_eth - caller: {}", ic_cdk::api::caller());
-        
-        // Get caller's Ethereum address for logging
-        let caller_eth_address = get_caller_ethereum_address().await?;
-        ic_cdk::println!("🔄 transfer_eth - caller_eth_address: {}", caller_eth_address);
+    Ok(check_derived_addresses(expected_address, derived))
+}
 
-        // Get nonce for caller's address
-        let nonce = self.fetch_caller_tx_nonce(&dest_chain).await?;
-        ic_cdk::println!("Caller nonce: {}", nonce);
+// Wrapper function to call ChainService send_eth_evm method
 
-        ic_cdk::println!("AMOUNT {}", amount);
 
-        // Estimate gas fees based on destination chain
-        let (gas_limit, max_fee_per_gas, max_priority_fee_per_gas) =
-            estimate_transaction_fees_for_chain(&dest_chain).await;
+pub async fn send_eth_evm(
+    to: String,
+    amount: f64,
+    dest_chain: String,
+    provenance: Option<Provenance>,
+) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
 
-        ic_cdk::println!("Gas fees - limit: {}, max_fee: {}, priority_fee: {}", gas_limit, max_fee_per_gas, max_priority_fee_per_gas);
+    validate_send_amount(amount)?;
+    let to = to_checksummed(&to)?;
+    let dest_chain = normalize_chain(&dest_chain)?;
 
-        // Get chain ID for the transaction
-        let (chain_id, rpc_url) = get_rpc_config(&dest_chain);
-        ic_cdk::println!("Building transaction for chain_id: {} with RPC: {}", chain_id, rpc_url);
+    // Amount is passed as ETH decimal string (e.g. "0.00008109335274785089")
+    chain_service()?
+        .send_eth_evm(to, amount.to_string(), dest_chain, provenance)
+        .await
+}
 
-        // Build the EIP-1559 transaction request
+// Controller-only: unblock a stuck nonce on the canister's bridge address
+#[ic_cdk::update]
+pub async fn clear_nonce(nonce: u64, dest_chain: String) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    require_controller()?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    chain_service()?.clear_nonce(nonce, dest_chain).await
+}
+
+/// How deeply `tx_hash` is buried on `dest_chain`, judged against that chain's
+/// `finality_blocks`
+#[ic_cdk::update]
+pub async fn get_tx_finality(tx_hash: String, dest_chain: String) -> Result<TxFinality, String> {
+    use crate::evm_indexer::chain_service;
+
+    let dest_chain = normalize_chain(&dest_chain)?;
+    let config = get_chain_config(&dest_chain);
+
+    let service = chain_service()?;
+
+    let block_number = service.fetch_receipt_block(&tx_hash, &dest_chain).await?;
+    let (confirmations, is_final) = match block_number {
+        Some(block) => {
+            let latest = service.fetch_block_number(&dest_chain).await?;
+            (
+                confirmations(block, latest),
+                is_final(&config, block, latest),
+            )
+        }
+        None => (0, false),
+    };
+
+    Ok(TxFinality {
+        block_number,
+        confirmations,
+        finality_blocks: config.finality_blocks,
+        is_final,
+    })
+}
+
+/// Refuse a withdrawal of `amount_wei` that is zero or more than the vault's `balance_wei`
+pub fn check_withdrawal(amount_wei: U256, balance_wei: U256) -> Result<(), String> {
+    if amount_wei.is_zero() {
+        return Err("Withdrawal amount must be greater than zero".to_string());
+    }
+    if amount_wei > balance_wei {
+        return Err(format!(
+            "Withdrawal of {} wei exceeds the vault balance of {} wei",
+            amount_wei, balance_wei
+        ));
+    }
+    Ok(())
+}
+
+// Controller-only: move bridge liquidity from the canister's own address to `to`
+#[ic_cdk::update]
+pub async fn withdraw(
+    to: String,
+    amount_wei: Nat,
+    dest_chain: String,
+    gas_overrides: Option<GasOverrides>,
+    nonce_override: Option<u64>,
+) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    require_controller()?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+    validate_eth_address(&to)?;
+    let to = to_checksummed(&to)?;
+    let amount_wei = U256::from_dec_str(&amount_wei.0.to_string())
+        .map_err(|e| format!("Invalid withdrawal amount: {}", e))?;
+
+    let service = chain_service()?;
+
+    let vault_address = get_canister_ethereum_address(&dest_chain).await?;
+    let balance = service
+        .fetch_balance(&vault_address, &dest_chain, BlockTag::Latest)
+        .await?;
+    check_withdrawal(amount_wei, balance)?;
+
+    service
+        .send_eth_evm_wei(
+            to,
+            amount_wei,
+            dest_chain,
+            None,
+            gas_overrides,
+            nonce_override,
+        )
+        .await
+}
+
+// Wrapper function to call ChainService send_eth_evm_wei with an exact wei amount
+pub async fn send_eth_evm_wei(
+    to: String,
+    amount_wei: U256,
+    dest_chain: String,
+    provenance: Option<Provenance>,
+    gas_overrides: Option<GasOverrides>,
+    nonce_override: Option<u64>,
+) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    let to = to_checksummed(&to)?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    chain_service()?
+        .send_eth_evm_wei(
+            to,
+            amount_wei,
+            dest_chain,
+            provenance,
+            gas_overrides,
+            nonce_override,
+        )
+        .await
+}
+
+/// Transaction count of the canister's vault on `dest_chain` at `block_tag`: its next nonce
+/// counting only mined transactions (`Latest`) or also queued ones (`Pending`)
+pub async fn vault_nonce(dest_chain: &str, block_tag: BlockTag) -> Result<u64, String> {
+    let nonce = crate::evm_indexer::chain_service()?
+        .fetch_tx_nonce(dest_chain, block_tag)
+        .await?;
+    nonce_to_u64(&nonce)
+}
+
+// Wrapper function to call ChainService send_erc20 for a registered token
+pub async fn send_erc20(
+    symbol: &str,
+    to: String,
+    amount: U256,
+    dest_chain: String,
+    gas_overrides: Option<GasOverrides>,
+    nonce_override: Option<u64>,
+) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    let to = to_checksummed(&to)?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    chain_service()?
+        .send_erc20(
+            symbol,
+            to,
+            amount,
+            dest_chain,
+            gas_overrides,
+            nonce_override,
+        )
+        .await
+}
+
+// Controller-only: resubmit the stored signed bytes of a dropped canister transaction
+#[ic_cdk::update]
+pub async fn rebroadcast(tx_hash: String, dest_chain: String) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    require_controller()?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    chain_service()?.rebroadcast(&tx_hash, &dest_chain).await
+}
+
+/// How a caller has used `transfer_eth` from its derived address
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CallerUsage {
+    pub transfers: u64,
+    /// Nanoseconds since the epoch
+    pub last_transfer: u64,
+}
+
+thread_local! {
+    // Principals refused by transfer_eth
+    static BLOCKED_PRINCIPALS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    // Successful transfer_eth sends per caller
+    static CALLER_USAGE: RefCell<BTreeMap<Principal, CallerUsage>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+pub fn blocked_principals_snapshot() -> BTreeSet<Principal> {
+    BLOCKED_PRINCIPALS.with(|blocked| blocked.borrow().clone())
+}
+
+pub fn restore_blocked_principals(blocked: BTreeSet<Principal>) {
+    BLOCKED_PRINCIPALS.with(|current| *current.borrow_mut() = blocked);
+}
+
+pub fn caller_usage_snapshot() -> BTreeMap<Principal, CallerUsage> {
+    CALLER_USAGE.with(|usage| usage.borrow().clone())
+}
+
+pub fn restore_caller_usage(usage: BTreeMap<Principal, CallerUsage>) {
+    CALLER_USAGE.with(|current| *current.borrow_mut() = usage);
+}
+
+/// Refuse callers an operator has blocked from sending from their derived address
+pub fn check_caller_allowed(caller: &Principal) -> Result<(), String> {
+    if BLOCKED_PRINCIPALS.with(|blocked| blocked.borrow().contains(caller)) {
+        return Err(format!("Caller {} is blocked", caller));
+    }
+    Ok(())
+}
+
+fn record_caller_transfer(caller: Principal, now: u64) {
+    CALLER_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        let entry = usage.entry(caller).or_insert(CallerUsage {
+            transfers: 0,
+            last_transfer: now,
+        });
+        entry.transfers += 1;
+        entry.last_transfer = now;
+    });
+}
+
+#[update]
+fn block_principal(principal: Principal) -> Result<(), String> {
+    require_controller()?;
+
+    BLOCKED_PRINCIPALS.with(|blocked| blocked.borrow_mut().insert(principal));
+    Ok(())
+}
+
+#[update]
+fn unblock_principal(principal: Principal) -> Result<(), String> {
+    require_controller()?;
+
+    BLOCKED_PRINCIPALS.with(|blocked| blocked.borrow_mut().remove(&principal));
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn list_blocked_principals() -> Vec<Principal> {
+    blocked_principals_snapshot().into_iter().collect()
+}
+
+/// Callers that have sent from their derived address, most recently active first
+#[ic_cdk::query]
+fn list_active_callers() -> Vec<(Principal, CallerUsage)> {
+    let mut callers: Vec<(Principal, CallerUsage)> = caller_usage_snapshot().into_iter().collect();
+    callers.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.last_transfer));
+    callers
+}
+
+// Wrapper function to call ChainService transfer_eth method with caller derivation path
+#[ic_cdk::update]
+pub async fn transfer_eth(to: String, amount: f64, dest_chain: String) -> Result<String, String> {
+    use crate::evm_indexer::chain_service;
+
+    let caller = ic_cdk::api::caller();
+    check_caller_allowed(&caller)?;
+    validate_send_amount(amount)?;
+    let to = to_checksummed(&to)?;
+    let dest_chain = normalize_chain(&dest_chain)?;
+
+    // Amount is passed as ETH decimal string (e.g. "0.00008109335274785089")
+    let tx_hash = chain_service()?
+        .transfer_eth(to, amount.to_string(), dest_chain)
+        .await?;
+    record_caller_transfer(caller, ic_cdk::api::time());
+    Ok(tx_hash)
+}
+
+// Key used for signing on a given chain; the name comes from the chain config
+// (test_key_1 for testnet/local deployments, key_1 for production keys)
+fn key_id(dest_chain: &str) -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: get_chain_config(dest_chain).ecdsa_key_name,
+    }
+}
+
+pub async fn holesky_txn() -> Result<String, String> {
+    let hash = TX_HASH.read().unwrap();
+    if let Some(ref txn) = *hash {
+        ic_cdk::println!("Returning latest Holesky tx hash: {}", txn);
+        Ok(txn.clone())
+    } else {
+        Err("No transaction hash stored.".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recipient_with_code_gets_the_contract_gas_limit() {
+        let code = format!("0x{}", "60".repeat(24_576));
+        assert!(code_is_present(&code));
+        assert_eq!(
+            native_gas_limit(Ok(code_is_present(&code)), 21_000),
+            CONTRACT_TRANSFER_GAS_LIMIT
+        );
+        assert_eq!(
+            native_gas_limit(Ok(code_is_present("0x")), 21_000),
+            NATIVE_TRANSFER_GAS_LIMIT
+        );
+        assert_eq!(native_gas_limit(Err("down".to_string()), 42), 42);
+    }
+
+    #[test]
+    fn base_fee_is_the_block_after_the_history() {
+        let history = |fees: Vec<u64>| FeeHistory {
+            reward: vec![],
+            gasUsedRatio: vec![0.5],
+            oldestBlock: Nat::from(100u8),
+            baseFeePerGas: fees.into_iter().map(Nat::from).collect(),
+        };
+        assert_eq!(
+            fee_history_base_fee(&history(vec![1_000_000_000, 1_125_000_000])),
+            Ok(U256::from(1_125_000_000u64))
+        );
+        assert!(fee_history_base_fee(&history(vec![])).is_err());
+    }
+
+    #[test]
+    fn normalize_chain_rejects_chains_without_a_config() {
+        assert_eq!(normalize_chain(" Holesky "), Ok("17000".to_string()));
+        assert_eq!(normalize_chain("8453"), Ok("8453".to_string()));
+        assert!(normalize_chain("base-sepolia").is_err());
+        assert!(normalize_chain("84532").is_err());
+        assert!(normalize_chain("+17000").is_err());
+        assert!(normalize_chain("mars").is_err());
+    }
+
+    #[test]
+    fn restored_chain_configs_are_supported() {
+        let config = ChainConfig {
+            chain_id: 84532,
+            rpc_url: "https://sepolia.base.org".to_string(),
+            ecdsa_key_name: "test_key_1".to_string(),
+            evm_contract: None,
+            finality_blocks: 60,
+            gas_limit_grace: None,
+        };
+        restore_chain_configs(HashMap::from([(84532, config)]));
+        assert_eq!(chain_configs_snapshot().len(), 1);
+        assert_eq!(normalize_chain("base-sepolia"), Ok("84532".to_string()));
+        assert_eq!(
+            get_chain_config("84532").rpc_url,
+            "https://sepolia.base.org"
+        );
+    }
+
+    #[test]
+    fn restored_token_configs_route_payouts() {
+        let config = TokenConfig {
+            dest_chain: "17000".to_string(),
+            symbol: "USDC".to_string(),
+            address: "0x52908400098527886E0F7030069857D2E4169EE7".to_string(),
+            decimals: 6,
+        };
+        restore_token_configs(HashMap::from([(
+            ("17000".to_string(), "USDC".to_string()),
+            config,
+        )]));
+        assert_eq!(token_configs_snapshot().len(), 1);
+        assert!(matches!(
+            payout_kind("17000", Some("usdc")),
+            Ok(PayoutKind::Erc20(token)) if token.decimals == 6
+        ));
+        assert!(payout_kind("8453", Some("USDC")).is_err());
+    }
+
+    #[test]
+    fn vault_entries_list_named_and_caller_vaults() {
+        let caller = Principal::from_slice(&[1, 2, 3]);
+        let entries = vault_entries(&["cold".to_string(), "hot".to_string()], &[caller]);
+        let labels: Vec<&str> = entries.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["canister", "cold", "hot", &format!("caller:{}", caller)]
+        );
+        assert_eq!(entries[0].1, Vec::<Vec<u8>>::new());
+        assert_eq!(entries[1].1, vec![b"vault".to_vec(), b"cold".to_vec()]);
+        assert_eq!(entries[3].1, vec![vec![1, 2, 3]]);
+
+        let callers: Vec<Principal> = (0..20).map(|i| Principal::from_slice(&[i])).collect();
+        let labels: Vec<String> = (0..20).map(|i| format!("vault{}", i)).collect();
+        assert_eq!(
+            vault_entries(&labels, &callers).len(),
+            MAX_VAULTS + MAX_CALLER_VAULTS
+        );
+    }
+
+    #[test]
+    fn restored_sent_txs_can_be_rebroadcast() {
+        let record = TxRecord {
+            raw_tx_hex: "02f8".to_string(),
+            dest_chain: "17000".to_string(),
+            sent_at: 7,
+        };
+        let encoded = candid::encode_one(HashMap::from([("0xabc".to_string(), record)])).unwrap();
+        restore_sent_txs(candid::decode_one(&encoded).unwrap());
+        let txs = sent_txs_snapshot();
+        assert_eq!(txs["0xabc"].raw_tx_hex, "02f8");
+        assert_eq!(txs["0xabc"].sent_at, 7);
+    }
+
+    #[test]
+    fn y_parity_recovers_the_signing_parity() {
+        use k256::ecdsa::SigningKey;
+
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let pubkey = key.verifying_key().to_sec1_bytes();
+        let prehash = [9u8; 32];
+        let (sig, recid) = key.sign_prehash_recoverable(&prehash).unwrap();
+        let sig = sig.to_bytes();
+
+        assert_eq!(
+            y_parity(&prehash, &sig, &pubkey),
+            Ok(recid.to_byte() as u64)
+        );
+    }
+
+    #[test]
+    fn y_parity_rejects_bad_inputs_without_trapping() {
+        use k256::ecdsa::SigningKey;
+
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let other = SigningKey::from_slice(&[8u8; 32]).unwrap();
+        let prehash = [9u8; 32];
+        let (sig, _) = key.sign_prehash_recoverable(&prehash).unwrap();
+        let sig = sig.to_bytes();
+
+        assert!(y_parity(&prehash, &sig, &[1, 2, 3]).is_err());
+        assert!(y_parity(&prehash, &[0u8; 10], &key.verifying_key().to_sec1_bytes()).is_err());
+        assert!(y_parity(&prehash, &sig, &other.verifying_key().to_sec1_bytes()).is_err());
+    }
+
+    #[test]
+    fn nonce_to_u64_rejects_nonces_beyond_u64() {
+        assert_eq!(nonce_to_u64(&Nat::from(7u64)), Ok(7));
+        assert_eq!(nonce_to_u64(&Nat::from(u64::MAX)), Ok(u64::MAX));
+
+        let too_big = Nat::from(u128::from(u64::MAX) + 1);
+        assert_eq!(
+            nonce_to_u64(&too_big),
+            Err("Nonce 18_446_744_073_709_551_616 does not fit in u64".to_string())
+        );
+    }
+
+    fn sample_chain_config(chain_id: u64, ecdsa_key_name: &str) -> ChainConfig {
+        ChainConfig {
+            chain_id,
+            rpc_url: format!("https://rpc.example/{}", chain_id),
+            ecdsa_key_name: ecdsa_key_name.to_string(),
+            evm_contract: None,
+            finality_blocks: 1,
+            gas_limit_grace: None,
+        }
+    }
+
+    #[test]
+    fn chains_with_different_key_names_derive_different_addresses() {
+        restore_chain_configs(HashMap::from([
+            (17000, sample_chain_config(17000, "test_key_1")),
+            (8453, sample_chain_config(8453, "key_1")),
+        ]));
+        assert_eq!(key_id("17000").name, "test_key_1");
+        assert_eq!(key_id("8453").name, "key_1");
+
+        // Stand-ins for the public keys the two threshold keys return
+        let public_key = |name: &str| {
+            let secret = ethers_core::utils::keccak256(name.as_bytes());
+            k256::ecdsa::SigningKey::from_slice(&secret)
+                .unwrap()
+                .verifying_key()
+                .to_sec1_bytes()
+        };
+        let holesky = pubkey_bytes_to_address(&public_key(&key_id("17000").name)).unwrap();
+        let base = pubkey_bytes_to_address(&public_key(&key_id("8453").name)).unwrap();
+        assert_ne!(holesky, base);
+    }
+
+    #[test]
+    fn normalize_chain_maps_each_alias_to_its_chain_id() {
+        restore_chain_configs(HashMap::from([(
+            84532,
+            sample_chain_config(84532, "test_key_1"),
+        )]));
+        for (alias, chain_id) in [
+            ("holesky", "17000"),
+            ("HOLESKY", "17000"),
+            ("base", "8453"),
+            ("Base", "8453"),
+            ("base-sepolia", "84532"),
+            ("17000", "17000"),
+        ] {
+            assert_eq!(
+                normalize_chain(alias),
+                Ok(chain_id.to_string()),
+                "{}",
+                alias
+            );
+        }
+        assert!(normalize_chain("ethereum")
+            .unwrap_err()
+            .starts_with("Unknown destination chain"));
+    }
+
+    #[test]
+    fn provenance_call_data_decodes_to_the_source_fields() {
+        let provenance = Provenance {
+            source_account: "GBRPYHIL2CI3FNQ4BXLFMNDLFJUNPU2HY3ZMFSHONUCEOASW7QC7OX2H".to_string(),
+            event_id: "0000000001-0000000001".to_string(),
+        };
+        let data = provenance_call_data(&provenance);
+
+        let decoded = ethabi::decode(
+            &[ethabi::ParamType::String, ethabi::ParamType::String],
+            &data,
+        )
+        .unwrap();
+        assert_eq!(
+            decoded,
+            vec![
+                Token::String(provenance.source_account),
+                Token::String(provenance.event_id)
+            ]
+        );
+    }
+
+    #[test]
+    fn clear_nonce_tx_is_a_zero_value_self_send_at_the_nonce() {
+        let canister = "0x4f159ACaC4105822E3201674FD2323320Bb9dd38";
+        let tx = build_clear_nonce_tx(canister, 42, 17000, "17000").unwrap();
+
+        assert_eq!(tx.to, Some(H160::from_str(canister).unwrap().into()));
+        assert_eq!(tx.nonce, Some(U256::from(42)));
+        assert_eq!(tx.value, Some(U256::zero()));
+        assert_eq!(tx.data, None);
+        assert_eq!(tx.chain_id, Some(U64::from(17000)));
+        let (_, max_fee, _) = transfer_fees_for_chain("17000");
+        assert_eq!(
+            tx.max_fee_per_gas,
+            Some(U256::from(max_fee * CLEAR_NONCE_FEE_MULTIPLIER))
+        );
+        assert!(build_clear_nonce_tx("not-an-address", 42, 17000, "17000").is_err());
+    }
+
+    #[test]
+    fn erc20_decimals_and_symbol_decode_from_call_data() {
+        let decimals = ethabi::encode(&[Token::Uint(U256::from(6))]);
+        assert_eq!(decode_erc20_decimals(&decimals), Ok(6));
+        let too_big = ethabi::encode(&[Token::Uint(U256::from(256))]);
+        assert!(decode_erc20_decimals(&too_big).is_err());
+        assert!(decode_erc20_decimals(&[]).is_err());
+
+        let symbol = ethabi::encode(&[Token::String("USDC".to_string())]);
+        assert_eq!(decode_erc20_symbol(&symbol), Ok("USDC".to_string()));
+        // Older tokens such as MKR return a zero-padded bytes32
+        let mut bytes32 = [0u8; 32];
+        bytes32[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_erc20_symbol(&bytes32), Ok("MKR".to_string()));
+        assert!(decode_erc20_symbol(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn inconsistent_send_with_an_accepted_hash_succeeds() {
+        use crate::evm_rpc_bindings::{
+            EthSepoliaService, JsonRpcError, RpcError, RpcService, SendRawTransactionStatus,
+        };
+
+        let hash = format!("0x{}", "ab".repeat(32));
+        let accepted =
+            || SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(Some(hash.clone())));
+        let results = vec![
+            (
+                RpcService::EthSepolia(EthSepoliaService::Alchemy),
+                accepted(),
+            ),
+            (
+                RpcService::EthSepolia(EthSepoliaService::Ankr),
+                SendRawTransactionResult::Err(RpcError::JsonRpcError(JsonRpcError {
+                    code: -32000,
+                    message: "already known".to_string(),
+                })),
+            ),
+            (
+                RpcService::EthSepolia(EthSepoliaService::PublicNode),
+                accepted(),
+            ),
+        ];
+        assert!(matches!(
+            resolve_inconsistent_send(results, &hash.to_uppercase().replace("0X", "0x")),
+            Ok(SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(
+                Some(_)
+            )))
+        ));
+
+        let split = vec![
+            (
+                RpcService::EthSepolia(EthSepoliaService::Alchemy),
+                SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow),
+            ),
+            (
+                RpcService::EthSepolia(EthSepoliaService::Ankr),
+                SendRawTransactionResult::Ok(SendRawTransactionStatus::InsufficientFunds),
+            ),
+        ];
+        assert!(resolve_inconsistent_send(split, &hash).is_err());
+    }
+
+    // Sign `tx` the way `sign_and_submit` does, with a local key standing in for the
+    // threshold key; returns the raw transaction and the signer's address
+    fn sign_locally(tx: &Eip1559TransactionRequest) -> (Vec<u8>, String) {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let public_key = key.verifying_key().to_sec1_bytes();
+        let unsigned_tx = typed_eip1559_payload(&tx.rlp()).unwrap();
+        let tx_hash = ethers_core::utils::keccak256(&unsigned_tx);
+        let (signature, _) = key.sign_prehash_recoverable(&tx_hash).unwrap();
+        let signature = signature.to_bytes();
+
+        check_signature_len(&signature).unwrap();
+        let sig = ethers_core::types::Signature {
+            r: U256::from_big_endian(&signature[0..32]),
+            s: U256::from_big_endian(&signature[32..64]),
+            v: y_parity(&tx_hash, &signature, &public_key).unwrap(),
+        };
+        let address = pubkey_bytes_to_address(&public_key).unwrap();
+        verify_signer(&tx_hash, &sig, &address).unwrap();
+        (
+            typed_eip1559_payload(&tx.rlp_signed(&sig)).unwrap(),
+            address,
+        )
+    }
+
+    #[test]
+    fn decoding_a_built_tx_returns_its_fields_and_sender() {
+        let to = "0x52908400098527886E0F7030069857D2E4169EE7";
         let tx = Eip1559TransactionRequest {
             from: None,
-            to: Some(H160::from_str(&to).map_err(|e| format!("Invalid address format: {}", e))?.into()),
-            nonce: Some(U256::from(nonce.0.to_u64().unwrap())),
-            gas: Some(U256::from(gas_limit)),
-            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
-            max_priority_fee_per_gas: Some(U256::from(max_priority_fee_per_gas)),
-            value: Some({
-                // Convert ETH decimal amount to wei for transaction
-                let eth_amount: f64 = amount.parse().map_err(|e| format!("Invalid ETH amount: {}", e))?;
-                let wei_amount = (eth_amount * 1e18) as u128;
-                U256::from(wei_amount)
-            }),
-            data: Default::default(),
+            to: Some(H160::from_str(to).unwrap().into()),
+            nonce: Some(U256::from(5)),
+            gas: Some(U256::from(21_000)),
+            max_fee_per_gas: Some(U256::from(20_000_000_000u64)),
+            max_priority_fee_per_gas: Some(U256::from(2_000_000_000u64)),
+            value: Some(U256::from(81_000_000_000_000u64)),
+            data: Some(vec![0xab, 0xcd].into()),
             access_list: vec![].into(),
-            chain_id: Some(U64::from(chain_id)),
+            chain_id: Some(U64::from(17000)),
         };
+        let (raw, address) = sign_locally(&tx);
 
-        // RLP encode the unsigned transaction and prefix with EIP1559 tx id (0x02)
-        let mut unsigned_tx = tx.rlp().to_vec();
-        unsigned_tx.insert(0, EIP1559_TX_ID);
+        let decoded = decode_signed_tx(format!("0x{}", hex::encode(raw))).unwrap();
+        assert_eq!(decoded.chain_id, 17000);
+        assert_eq!(decoded.nonce, 5);
+        assert_eq!(decoded.to, Some(to.to_string()));
+        assert_eq!(decoded.value_wei, "81000000000000");
+        assert_eq!(decoded.gas, 21_000);
+        assert_eq!(decoded.max_fee, 20_000_000_000);
+        assert_eq!(decoded.max_priority_fee, 2_000_000_000);
+        assert_eq!(decoded.data, "0xabcd");
+        assert_eq!(decoded.recovered_from, address);
 
-        // Generate the transaction hash to sign (keccak256 of the unsigned_tx)
-        let tx_hash = ethers_core::utils::keccak256(&unsigned_tx);
+        assert!(decode_signed_tx("0xf86c".to_string()).is_err());
+    }
 
-        // Sign the transaction hash with caller's IC ECDSA key (using derivation path)
-        let (public_key_bytes, signature) = self.caller_pubkey_and_signature(tx_hash.to_vec()).await?;
+    #[test]
+    fn zero_and_own_addresses_are_rejected_as_recipients() {
+        assert_eq!(
+            validate_eth_address("0x0000000000000000000000000000000000000000"),
+            Err("Recipient is the zero address".to_string())
+        );
 
-        // Recover y parity (v) from signature
-        let y_parity = y_parity(&tx_hash, &signature.signature, &public_key_bytes);
+        let own = "0x99a79158A40E4BEF8Beb3AcFAE893e62C45034E8";
+        let recipient = validate_eth_address(own).unwrap();
+        assert_eq!(
+            check_not_own_address(&recipient, &own.to_lowercase()),
+            Err("Recipient is the bridge's own address".to_string())
+        );
+        let other = validate_eth_address("0x0000000000000000000000000000000000000001").unwrap();
+        assert_eq!(check_not_own_address(&other, own), Ok(()));
+    }
 
-        // Create ethers Signature struct with r,s,v
-        let sig = ethers_core::types::Signature {
-            r: U256::from_big_endian(&signature.signature[0..32]),
-            s: U256::from_big_endian(&signature.signature[32..64]),
-            v: y_parity as u64,
-        };
+    #[test]
+    fn supported_chains_default_to_holesky_and_base() {
+        let chains = get_supported_chains();
+        let summary: Vec<(u64, &str, bool)> = chains
+            .iter()
+            .map(|chain| (chain.chain_id, chain.name.as_str(), chain.is_testnet))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(8453, "base", false), (17000, "holesky", true)]
+        );
+        assert_eq!(chains[0].rpc_url, "https://base.drpc.org");
+        assert_eq!(
+            chains[1].rpc_url,
+            "https://ethereum-holesky-rpc.publicnode.com"
+        );
+        assert!(chains.iter().all(|chain| chain.evm_contract.is_none()));
+    }
 
-        // RLP encode the signed transaction and prefix with tx id
-        let mut signed_tx = tx.rlp_signed(&sig).to_vec();
-        signed_tx.insert(0, EIP1559_TX_ID);
+    #[test]
+    fn wrong_length_signatures_are_rejected_cleanly() {
+        assert_eq!(check_signature_len(&[0u8; 64]), Ok(()));
+        assert_eq!(
+            check_signature_len(&[0u8; 63]),
+            Err("Unexpected ECDSA signature length: 63 bytes (expected 64)".to_string())
+        );
+        assert!(check_signature_len(&[]).is_err());
+        assert!(check_signature_len(&[0u8; 65]).is_err());
+    }
 
-        let raw_tx_hex = format!("0x{}", hex::encode(&signed_tx));
+    #[test]
+    fn units_format_and_parse_exactly() {
+        let ether = U256::exp10(18);
+        assert_eq!(
+            format_units(U256::one(), ETH_DECIMALS),
+            "0.000000000000000001"
+        );
+        assert_eq!(format_units(ether, ETH_DECIMALS), "1");
+        assert_eq!(
+            format_units(U256::from(1_234_567_890_123_456_789u128), ETH_DECIMALS),
+            "1.234567890123456789"
+        );
 
-        ic_cdk::println!("Raw signed transaction hex: {}", raw_tx_hex);
+        assert_eq!(
+            parse_units("0.000000000000000001", ETH_DECIMALS),
+            Ok(U256::one())
+        );
+        assert_eq!(parse_units("1", ETH_DECIMALS), Ok(ether));
+        assert_eq!(
+            parse_units("1.234567890123456789", ETH_DECIMALS),
+            Ok(U256::from(1_234_567_890_123_456_789u128))
+        );
+        assert!(parse_units("0.0000000000000000001", ETH_DECIMALS).is_err());
+        assert!(parse_units("1.2.3", ETH_DECIMALS).is_err());
+    }
 
-        let cycles_to_pay: u128 = 600_000_000_000;
+    #[test]
+    fn each_chain_applies_its_own_finality_threshold() {
+        let mut base = sample_chain_config(8453, "key_1");
+        base.finality_blocks = BASE_FINALITY_BLOCKS;
+        let mut holesky = sample_chain_config(17000, "test_key_1");
+        holesky.finality_blocks = HOLESKY_FINALITY_BLOCKS;
+
+        // 12 confirmations finalize on Holesky but not on Base, which needs 60
+        assert!(is_final(&holesky, 100, 111));
+        assert!(!is_final(&base, 100, 111));
+        assert!(!is_final(&holesky, 100, 110));
+        assert!(is_final(&base, 100, 159));
+        assert_eq!(confirmations(101, 100), 0);
+    }
+
+    #[test]
+    fn wei_conversion_rejects_nan_negative_and_huge_amounts() {
+        assert_eq!(
+            eth_to_wei_f64(0.5),
+            Ok(U256::from(500_000_000_000_000_000u128))
+        );
+        assert!(eth_to_wei_f64(f64::NAN).unwrap_err().contains("finite"));
+        assert!(eth_to_wei_f64(f64::INFINITY)
+            .unwrap_err()
+            .contains("finite"));
+        assert!(eth_to_wei_f64(-1.0).unwrap_err().contains("negative"));
+        assert!(eth_to_wei_f64(1e30).unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn send_amounts_must_be_positive_and_finite() {
+        assert_eq!(validate_send_amount(0.01), Ok(()));
+        for amount in [-0.01, 0.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(validate_send_amount(amount).is_err(), "{} accepted", amount);
+        }
+    }
+
+    #[test]
+    fn changed_key_name_is_reported_as_an_address_mismatch() {
+        let address_for = |name: &str| {
+            let secret = ethers_core::utils::keccak256(name.as_bytes());
+            let key = k256::ecdsa::SigningKey::from_slice(&secret).unwrap();
+            pubkey_bytes_to_address(&key.verifying_key().to_sec1_bytes()).unwrap()
+        };
+        let expected = address_for("key_1");
+
+        let check = check_derived_addresses(
+            &expected.to_lowercase(),
+            vec![
+                (8453, "key_1".to_string(), address_for("key_1")),
+                (17000, "test_key_1".to_string(), address_for("test_key_1")),
+            ],
+        );
+        assert!(check.chains[0].matches);
+        assert!(!check.chains[1].matches);
+        assert!(check
+            .warning
+            .unwrap()
+            .contains("chain 17000 (key test_key_1) derives"));
+    }
 
-        // Get RPC configuration for sending the transaction
-        let (chain_id, rpc_url) = get_rpc_config(&dest_chain);
-        ic_cdk::println!(
-            "Sending transaction to chain_id: {} with rpc_url: {}",
-            chain_id,
-            rpc_url
+    #[test]
+    fn withdrawals_need_a_controller_and_enough_balance() {
+        assert_eq!(
+            crate::check_controller(false),
+            Err("Caller is not a controller of this canister".to_string())
         );
+        assert_eq!(crate::check_controller(true), Ok(()));
 
-        // Send the raw transaction using evm_rpc canister's eth_sendRawTransaction
-        let (send_result,) = call_with_payment128::<
-            (
-                RpcServices,
-                Option<crate::evm_rpc_bindings::RpcConfig>,
-                String,
-            ),
-            (MultiSendRawTransactionResult,),
-        >(
-            self.evm_rpc.0,
-            "eth_sendRawTransaction",
-            (
-                RpcServices::Custom {
-                    chainId: chain_id,
-                    services: vec![RpcApi {
-                        url: rpc_url,
-                        headers: None,
-                    }],
-                },
-                None,
-                raw_tx_hex,
-            ),
-            cycles_to_pay,
-        )
-        .await
-        .map_err(|e| format!("Failed to send raw transaction: {:?}", e))?;
+        let balance = U256::from(1_000u64);
+        assert_eq!(check_withdrawal(U256::from(1_000u64), balance), Ok(()));
+        assert_eq!(
+            check_withdrawal(U256::from(1_001u64), balance),
+            Err("Withdrawal of 1001 wei exceeds the vault balance of 1000 wei".to_string())
+        );
+        assert!(check_withdrawal(U256::zero(), balance).is_err());
+    }
 
-        // Parse result from send
-        match send_result {
-            MultiSendRawTransactionResult::Consistent(send_status) => match send_status {
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(opt_tx_hash)) => {
-                    if let Some(tx_hash) = opt_tx_hash {
-                        ic_cdk::println!(
-                            "✅ Transfer transaction sent successfully, tx hash: {:?}",
-                            tx_hash
-                        );
-                        Ok(tx_hash)
-                    } else {
-                        Err("Error: transaction hash not found in the response".to_string())
-                    }
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooLow) => {
-                    Err("Error: nonce too low".to_string())
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::NonceTooHigh) => {
-                    Err("Error: nonce too high".to_string())
-                }
-                SendRawTransactionResult::Ok(SendRawTransactionStatus::InsufficientFunds) => {
-                    Err("Error: insufficient funds".to_string())
-                }
-                SendRawTransactionResult::Err(rpc_error) => {
-                    Err(format!("RPC error sending transaction: {:?}", rpc_error))
-                }
-            },
-            MultiSendRawTransactionResult::Inconsistent(_) => {
-                Err("Inconsistent send raw transaction results".to_string())
-            }
+    #[test]
+    fn addresses_checksum_the_same_from_any_case() {
+        let checksummed = "0x52908400098527886E0F7030069857D2E4169EE7";
+        for input in [
+            checksummed.to_string(),
+            checksummed.to_lowercase(),
+            "0x52908400098527886e0F7030069857D2e4169Ee7".to_string(),
+        ] {
+            assert_eq!(to_checksummed(&input).as_deref(), Ok(checksummed));
         }
+        assert!(to_checksummed("0x1234").is_err());
     }
 
-}
+    #[test]
+    fn erc20_balance_below_the_payout_is_rejected() {
+        let data = ethabi::encode(&[Token::Uint(U256::from(2_500_000u64))]);
+        let balance = decode_erc20_balance(&data).unwrap();
+        assert_eq!(balance, U256::from(2_500_000u64));
 
+        assert_eq!(
+            check_token_balance("USDC", U256::from(2_500_000u64), balance),
+            Ok(())
+        );
+        assert_eq!(
+            check_token_balance("USDC", U256::from(3_000_000u64), balance),
+            Err(
+                "Transfer of 3000000 USDC base units exceeds the vault balance of 2500000"
+                    .to_string()
+            )
+        );
+        assert!(decode_erc20_balance(&[1, 2, 3]).is_err());
+    }
 
-#[update]
-pub async fn generate_canister_key_pair_evm() -> Result<String, String> {
-    let (_, ecdsa_key) = get_network_config();
+    #[test]
+    fn simulated_revert_stops_the_send() {
+        // Error(string) with the reason "insufficient liquidity"
+        let revert_data = [
+            ethers_core::utils::id("Error(string)").to_vec(),
+            ethabi::encode(&[Token::String("insufficient liquidity".to_string())]),
+        ]
+        .concat();
+        let reverted = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": 3,
+                "message": "execution reverted",
+                "data": format!("0x{}", hex::encode(revert_data)),
+            },
+        });
+        assert_eq!(
+            simulation_result(&reverted),
+            Err("Simulation reverted, transaction not sent: insufficient liquidity".to_string())
+        );
 
-    let request = EcdsaPublicKeyArgument {
-        key_id: EcdsaKeyId {
-            curve: EcdsaCurve::Secp256k1,
-            name: ecdsa_key.to_string(),
-        },
-        derivation_path: vec![],
-        ..Default::default()
-    };
+        let ok = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "0x" });
+        assert_eq!(simulation_result(&ok), Ok(()));
+    }
 
-    let (response,) = ecdsa_public_key(request)
-        .await
-        .map_err(|e| format!("ecdsa_public_key failed {:?}", e))?;
+    #[test]
+    fn empty_or_malformed_public_keys_are_errors() {
+        assert_eq!(
+            pubkey_bytes_to_address(&[]),
+            Err("ECDSA public key is empty".to_string())
+        );
+        assert_eq!(
+            pubkey_bytes_to_address(&[0x05; 33]),
+            Err("Malformed ECDSA public key: 33 bytes with prefix Some(05)".to_string())
+        );
+        assert!(pubkey_bytes_to_address(&[0x02; 20]).is_err());
+    }
 
-    ic_cdk::println!("ECDSA public key response: {:?}", response);
+    // Poll `future` once; async paths that finish without an outcall or timer are Ready
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        std::pin::pin!(future).poll(&mut std::task::Context::from_waker(Waker::noop()))
+    }
 
-    let public_key_hex = hex::encode(&response.public_key);
+    #[test]
+    fn signing_is_retried_after_a_transient_failure() {
+        let calls = RefCell::new(0);
+        let signer = || {
+            *calls.borrow_mut() += 1;
+            let attempt = *calls.borrow();
+            async move {
+                if attempt == 1 {
+                    Err((RejectionCode::SysTransient, "subnet busy".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        };
+        assert_eq!(
+            poll_once(retry_signing(3, Duration::ZERO, signer)),
+            Poll::Ready(Ok(2))
+        );
 
-    ic_cdk::println!("Derived public key hex: {}", public_key_hex);
+        // A rejection caused by the request itself is not retried
+        *calls.borrow_mut() = 0;
+        let rejecting = || {
+            *calls.borrow_mut() += 1;
+            async { Err::<u32, _>((RejectionCode::CanisterReject, "bad key".to_string())) }
+        };
+        assert!(matches!(
+            poll_once(retry_signing(3, Duration::ZERO, rejecting)),
+            Poll::Ready(Err(_))
+        ));
+        assert_eq!(*calls.borrow(), 1);
+    }
 
-    let ethereum_address = pubkey_bytes_to_address(&response.public_key);
+    #[test]
+    fn blocked_callers_are_rejected_until_unblocked() {
+        let caller = Principal::from_slice(&[5; 29]);
+        assert_eq!(check_caller_allowed(&caller), Ok(()));
 
-    Ok(ethereum_address)
-}
+        restore_blocked_principals(BTreeSet::from([caller]));
+        assert_eq!(
+            check_caller_allowed(&caller),
+            Err(format!("Caller {} is blocked", caller))
+        );
+        assert_eq!(check_caller_allowed(&Principal::anonymous()), Ok(()));
 
+        restore_blocked_principals(BTreeSet::new());
+        assert_eq!(check_caller_allowed(&caller), Ok(()));
+    }
 
-#[update]
-pub async fn generate_key_pair_evm() -> Result<String, String> {
-    let (_, ecdsa_key) = get_network_config();
+    #[test]
+    fn chosen_block_tag_reaches_the_rpc_args() {
+        let address = "0x52908400098527886E0F7030069857D2E4169EE7";
+        assert_eq!(
+            balance_params(address, &BlockTag::Safe),
+            serde_json::json!([address, "safe"])
+        );
+        assert_eq!(
+            balance_params(address, &BlockTag::Finalized),
+            serde_json::json!([address, "finalized"])
+        );
+        assert_eq!(
+            balance_params(address, &BlockTag::Number(Nat::from(255u32))),
+            serde_json::json!([address, "0xff"])
+        );
+    }
 
-    let request = EcdsaPublicKeyArgument {
-        key_id: EcdsaKeyId {
-            curve: EcdsaCurve::Secp256k1,
-            name: ecdsa_key.to_string(),
-        },
-        derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
-        ..Default::default()
-    };
+    #[test]
+    fn cycles_rejections_map_to_the_friendly_error() {
+        let rejection = (
+            RejectionCode::CanisterReject,
+            "Insufficient cycles attached: 1000 required".to_string(),
+        );
+        assert_eq!(
+            rpc_call_error("eth_sendRawTransaction", 500, "Send failed", rejection),
+            "Insufficient cycles for outcall: increase cycles budget for eth_sendRawTransaction (500 attached)"
+        );
 
-    let (response,) = ecdsa_public_key(request)
-        .await
-        .map_err(|e| format!("ecdsa_public_key failed {:?}", e))?;
+        let other = (RejectionCode::CanisterError, "canister trapped".to_string());
+        assert!(rpc_call_error("eth_call", 500, "Call failed", other)
+            .starts_with("Call failed: (CanisterError"));
 
-    ic_cdk::println!("ECDSA public key response: {:?}", response);
+        let too_few = RpcError::ProviderError(ProviderError::TooFewCycles {
+            expected: Nat::from(2_000u32),
+            received: Nat::from(1_000u32),
+        });
+        assert_eq!(
+            too_few_cycles_error("eth_getBalance", &too_few).as_deref(),
+            Some("Insufficient cycles for outcall: increase cycles budget for eth_getBalance (required 2_000, attached 1_000)")
+        );
+    }
 
-    let public_key_hex = hex::encode(&response.public_key);
+    #[test]
+    fn ens_name_resolves_through_a_mocked_resolver() {
+        let registry = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+        let resolver = H160::from_low_u64_be(0xabc);
+        let owner = H160::from_str("0x52908400098527886E0F7030069857D2E4169EE7").unwrap();
+        let node = ens_namehash("vitalik.eth").unwrap();
 
-    ic_cdk::println!("Derived public key hex: {}", public_key_hex);
+        let calls = RefCell::new(Vec::new());
+        let mock = |to: String, data: Vec<u8>| {
+            calls.borrow_mut().push(to.clone());
+            assert_eq!(&data[4..], node.as_slice());
+            let answer = if data[..4] == ethers_core::utils::id("resolver(bytes32)") {
+                resolver
+            } else {
+                owner
+            };
+            async move { Ok(ethabi::encode(&[Token::Address(answer)])) }
+        };
 
-    let ethereum_address = pubkey_bytes_to_address(&response.public_key);
+        assert_eq!(
+            poll_once(resolve_ens_with("vitalik.eth", registry, mock)),
+            Poll::Ready(Ok("0x52908400098527886E0F7030069857D2E4169EE7".to_string()))
+        );
+        assert_eq!(
+            calls.into_inner(),
+            vec![registry.to_string(), format!("{:?}", resolver)]
+        );
+        assert!(looks_like_ens_name("vitalik.eth"));
+        assert!(!looks_like_ens_name(
+            "0x52908400098527886E0F7030069857D2E4169EE7"
+        ));
+    }
 
-    Ok(ethereum_address)
-}
+    #[test]
+    fn max_fee_override_keeps_the_other_estimates() {
+        let estimated = (25_200, 20_000_000_000, 2_000_000_000);
+        let overrides = GasOverrides {
+            gas_limit: None,
+            max_fee_per_gas: Some(50_000_000_000),
+            max_priority_fee_per_gas: None,
+        };
+        assert_eq!(
+            apply_gas_overrides(estimated, Some(&overrides)),
+            Ok((25_200, 50_000_000_000, 2_000_000_000))
+        );
+        assert_eq!(apply_gas_overrides(estimated, None), Ok(estimated));
 
-/// Derive Ethereum address from uncompressed secp256k1 public key bytes (65 bytes, 0x04 prefix)
+        let too_low = GasOverrides {
+            max_fee_per_gas: Some(1_000_000_000),
+            ..overrides
+        };
+        assert_eq!(
+            apply_gas_overrides(estimated, Some(&too_low)),
+            Err("Priority fee 2000000000 exceeds max fee 1000000000".to_string())
+        );
+    }
 
-fn pubkey_bytes_to_address(pubkey_bytes: &[u8]) -> String {
-    use k256::elliptic_curve::sec1::ToEncodedPoint;
-    use sha3::Keccak256;
+    #[test]
+    fn chain_configs_update_in_place_and_reject_invalid_ones() {
+        assert!(
+            insert_chain_config(sample_chain_config(84532, "test_key_1"))
+                .unwrap()
+                .is_none()
+        );
+        let previous = insert_chain_config(sample_chain_config(84532, "key_1")).unwrap();
+        assert_eq!(
+            previous.map(|config| config.ecdsa_key_name).as_deref(),
+            Some("test_key_1")
+        );
+        assert_eq!(get_chain_config("84532").ecdsa_key_name, "key_1");
 
-    let key =
-        PublicKey::from_sec1_bytes(pubkey_bytes).expect("failed to parse the public key as SEC1");
-    let point = key.to_encoded_point(false);
-    let point_bytes = point.as_bytes();
-    assert_eq!(point_bytes[0], 0x04);
+        assert_eq!(
+            insert_chain_config(sample_chain_config(0, "key_1")).unwrap_err(),
+            "Chain id must be non-zero"
+        );
+        let mut bad_url = sample_chain_config(84532, "test_key_1");
+        bad_url.rpc_url = "ftp://rpc.example".to_string();
+        assert!(insert_chain_config(bad_url).is_err());
+        assert_eq!(get_chain_config("84532").ecdsa_key_name, "key_1");
+    }
 
-    let hash = Keccak256::digest(&point_bytes[1..]);
+    #[test]
+    fn warmed_up_keys_are_reused_without_rederiving() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let public_key = key.verifying_key().to_sec1_bytes().to_vec();
+        let address = pubkey_bytes_to_address(&public_key).unwrap();
+        // What warm_up leaves behind for the default chains' key
+        CANISTER_PUBLIC_KEYS.with(|keys| {
+            keys.borrow_mut().insert(
+                key_id("17000").name,
+                EcdsaPublicKeyResponse {
+                    public_key,
+                    chain_code: vec![],
+                },
+            )
+        });
+        assert_eq!(key_id("17000").name, key_id("8453").name);
 
-    let address = Address::from_slice(&hash[12..32]);
-    ethers_core::utils::to_checksum(&address.into(), None)
-}
+        // A derivation would call the management canister, which cannot complete here
+        assert_eq!(
+            poll_once(get_canister_ethereum_address("17000")),
+            Poll::Ready(Ok(address.clone()))
+        );
+        assert_eq!(
+            poll_once(warm_up_canister_keys()),
+            Poll::Ready(Ok(vec![(8453, address.clone()), (17000, address)]))
+        );
+    }
 
-pub async fn estimate_transaction_fees() -> (u128, u128, u128) {
-    const GAS_LIMIT: u128 = 1_500_000; // 150k gas
-    const MAX_FEE_PER_GAS: u128 = 200_000_000_000; // 200 Gwei
-    const MAX_PRIORITY_FEE_PER_GAS: u128 = 50_000_000_000; // 50 Gwei
+    #[test]
+    fn zero_value_empty_data_tx_has_one_type_prefix() {
+        use ethers_core::utils::rlp::Rlp;
 
-    (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
-}
+        let tx = Eip1559TransactionRequest {
+            from: None,
+            to: Some(H160::from_low_u64_be(1).into()),
+            nonce: Some(U256::zero()),
+            gas: Some(U256::from(21_000)),
+            max_fee_per_gas: Some(U256::from(20_000_000_000u64)),
+            max_priority_fee_per_gas: Some(U256::from(2_000_000_000u64)),
+            value: Some(U256::zero()),
+            data: None,
+            access_list: vec![].into(),
+            chain_id: Some(U64::from(17000)),
+        };
+        let unsigned_tx = typed_eip1559_payload(&tx.rlp()).unwrap();
+        let (signed_tx, _) = sign_locally(&tx);
 
-pub async fn estimate_transaction_fees_for_chain(dest_chain: &str) -> (u128, u128, u128) {
-    match dest_chain {
-        "17000" => {
-            // Holesky Testnet - Lower fees for testnet
-            const GAS_LIMIT: u128 = 21_000; // Standard ETH transfer
-            const MAX_FEE_PER_GAS: u128 = 20_000_000_000; // 20 Gwei
-            const MAX_PRIORITY_FEE_PER_GAS: u128 = 2_000_000_000; // 2 Gwei
-            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
-        },
-        "8453" => {
-            // Base Mainnet - Optimized fees
-            const GAS_LIMIT: u128 = 21_000; // Standard ETH transfer
-            const MAX_FEE_PER_GAS: u128 = 1_000_000_000; // 1 Gwei (Base is cheaper)
-            const MAX_PRIORITY_FEE_PER_GAS: u128 = 1_000_000_000; // 1 Gwei
-            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
-        },
-        _ => {
-            // Default fallback
-            const GAS_LIMIT: u128 = 21_000;
-            const MAX_FEE_PER_GAS: u128 = 20_000_000_000; // 20 Gwei
-            const MAX_PRIORITY_FEE_PER_GAS: u128 = 2_000_000_000; // 2 Gwei
-            (GAS_LIMIT, MAX_FEE_PER_GAS, MAX_PRIORITY_FEE_PER_GAS)
+        for (payload, fields) in [(&unsigned_tx, 9), (&signed_tx, 12)] {
+            assert_eq!(payload[0], EIP1559_TX_ID);
+            assert!(payload[1] >= RLP_LIST_PREFIX, "a second type byte follows");
+            let list = Rlp::new(&payload[1..]);
+            assert_eq!(list.item_count().unwrap(), fields);
+            // chain_id, nonce, ..., value and data in EIP-1559 field order
+            assert_eq!(list.val_at::<u64>(0).unwrap(), 17000);
+            assert_eq!(list.val_at::<U256>(6).unwrap(), U256::zero());
+            assert!(list.val_at::<Vec<u8>>(7).unwrap().is_empty());
         }
+        // Re-wrapping a typed payload does not add a second prefix
+        assert_eq!(typed_eip1559_payload(&unsigned_tx).unwrap(), unsigned_tx);
     }
-}
 
-fn y_parity(prehash: &[u8], sig: &[u8], pubkey: &[u8]) -> u64 {
-    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+    #[test]
+    fn send_accepted_by_one_of_two_providers_succeeds() {
+        use crate::evm_rpc_bindings::{
+            EthSepoliaService, JsonRpcError, RpcError, RpcService, SendRawTransactionStatus,
+        };
 
-    let orig_key = VerifyingKey::from_sec1_bytes(pubkey).expect("failed to parse the pubkey");
-    let signature = Signature::try_from(sig).unwrap();
-    for parity in [0u8, 1] {
-        let recid = RecoveryId::try_from(parity).unwrap();
-        let recovered_key = VerifyingKey::recover_from_prehash(prehash, &signature, recid)
-            .expect("failed to recover key");
-        if recovered_key == orig_key {
-            return parity as u64;
-        }
-    }
+        let raw_tx_hex = "0x02abcd";
+        let expected_hash = signed_tx_hash(raw_tx_hex).unwrap();
+        assert_eq!(
+            expected_hash,
+            format!(
+                "0x{}",
+                hex::encode(ethers_core::utils::keccak256([0x02, 0xab, 0xcd]))
+            )
+        );
+        assert_eq!(signed_tx_hash("02abcd"), Ok(expected_hash.clone()));
 
-    panic!(
-        "failed to recover the parity bit from a signature; sig: {}, pubkey: {}",
-        hex::encode(sig),
-        hex::encode(pubkey)
-    )
-}
+        let rejected = || {
+            SendRawTransactionResult::Err(RpcError::JsonRpcError(JsonRpcError {
+                code: -32000,
+                message: "rate limited".to_string(),
+            }))
+        };
+        let mixed = vec![
+            (
+                RpcService::EthSepolia(EthSepoliaService::Alchemy),
+                rejected(),
+            ),
+            (
+                RpcService::EthSepolia(EthSepoliaService::Ankr),
+                SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(Some(
+                    expected_hash.clone(),
+                ))),
+            ),
+        ];
+        assert!(matches!(
+            resolve_inconsistent_send(mixed, &expected_hash),
+            Ok(SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(
+                Some(hash)
+            ))) if hash == expected_hash
+        ));
 
-const NETWORK: &str = "mainnet";
-pub fn get_network_config() -> (&'static str, &'static str) {
-    match NETWORK {
-        "local" => (
-            "0xDa824f554C42ecd28a74A037c70FA0b5bf447bB0", // address_local
-            "dfx_test_key",                               // ecdsa_key_local
-        ),
-        "mainnet" => (
-            "0x4f159ACaC4105822E3201674FD2323320Bb9dd38", // address_main
-            "test_key_1",                                 // ecdsa_key_main
-        ),
-        _ => panic!("Unknown network!"),
+        // A provider reporting some other hash did not accept this transaction
+        let wrong_hash = vec![
+            (
+                RpcService::EthSepolia(EthSepoliaService::Alchemy),
+                rejected(),
+            ),
+            (
+                RpcService::EthSepolia(EthSepoliaService::Ankr),
+                SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(Some(format!(
+                    "0x{}",
+                    "00".repeat(32)
+                )))),
+            ),
+        ];
+        assert!(resolve_inconsistent_send(wrong_hash, &expected_hash).is_err());
     }
-}
 
-pub async fn get_ecdsa_public_key() -> Result<EcdsaPublicKeyResponse, String> {
-    let res = ecdsa_public_key(EcdsaPublicKeyArgument {
-        key_id: key_id(),
-        ..Default::default()
-    })
-    .await
-    .map_err(|e| format!("Failed to get public key: {:?}", e))?;
+    #[test]
+    fn recipient_allowlist_gates_payouts_only_while_enabled() {
+        let listed = H160::from_str("0x52908400098527886E0F7030069857D2E4169EE7").unwrap();
+        let unlisted = H160::from_str("0x742d35Cc6634C0532925a3b8D29435B7b6c8ceB3").unwrap();
+        let addresses = BTreeSet::from([ethers_core::utils::to_checksum(&listed, None)]);
 
-    Ok(res.0)
-}
+        restore_recipient_allowlist(RecipientAllowlist {
+            enabled: false,
+            addresses: addresses.clone(),
+        });
+        assert!(check_recipient_allowed(&unlisted).is_ok());
 
-pub async fn get_caller_ecdsa_public_key() -> Result<EcdsaPublicKeyResponse, String> {
-    let res = ecdsa_public_key(EcdsaPublicKeyArgument {
-        key_id: key_id(),
-        derivation_path: vec![ic_cdk::api::caller().as_slice().to_vec()],
-        ..Default::default()
-    })
-    .await
-    .map_err(|e| format!("Failed to get caller public key: {:?}", e))?;
+        restore_recipient_allowlist(RecipientAllowlist {
+            enabled: true,
+            addresses,
+        });
+        assert!(check_recipient_allowed(&listed).is_ok());
+        assert_eq!(
+            check_recipient_allowed(&unlisted),
+            Err(format!(
+                "Recipient {} is not on the allowlist",
+                ethers_core::utils::to_checksum(&unlisted, None)
+            ))
+        );
+    }
 
-    Ok(res.0)
-}
+    #[test]
+    fn nonce_override_is_used_instead_of_the_pending_nonce() {
+        let fetched = RefCell::new(Vec::new());
+        let fetch_nonce = |block_tag: BlockTag| {
+            fetched.borrow_mut().push(format!("{:?}", block_tag));
+            let nonce = if matches!(block_tag, BlockTag::Pending) {
+                9
+            } else {
+                7
+            };
+            async move { Ok(Nat::from(nonce as u64)) }
+        };
 
-pub async fn get_caller_ethereum_address() -> Result<String, String> {
-    let public_key_response = get_caller_ecdsa_public_key().await?;
-    let ethereum_address = pubkey_bytes_to_address(&public_key_response.public_key);
-    Ok(ethereum_address)
-}
+        assert_eq!(
+            poll_once(choose_nonce(None, fetch_nonce)),
+            Poll::Ready(Ok(9))
+        );
+        assert_eq!(fetched.take(), vec!["Pending"]);
 
-// Wrapper function to call ChainService send_eth_evm method
+        // Pinned: only the confirmed nonce is fetched, to validate the override
+        assert_eq!(
+            poll_once(choose_nonce(Some(12), fetch_nonce)),
+            Poll::Ready(Ok(12))
+        );
+        assert_eq!(fetched.take(), vec!["Latest"]);
+        assert_eq!(
+            poll_once(choose_nonce(Some(6), fetch_nonce)),
+            Poll::Ready(Err(
+                "Nonce 6 is already confirmed (current nonce is 7)".to_string()
+            ))
+        );
+    }
 
+    #[test]
+    fn tampered_signature_does_not_recover_the_signer() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let public_key = key.verifying_key().to_sec1_bytes();
+        let address = pubkey_bytes_to_address(&public_key).unwrap();
+        let tx_hash = ethers_core::utils::keccak256(b"transfer");
+        let (signature, _) = key.sign_prehash_recoverable(&tx_hash).unwrap();
+        let signature = signature.to_bytes();
+        let sig = ethers_core::types::Signature {
+            r: U256::from_big_endian(&signature[0..32]),
+            s: U256::from_big_endian(&signature[32..64]),
+            v: y_parity(&tx_hash, &signature, &public_key).unwrap(),
+        };
+        assert_eq!(verify_signer(&tx_hash, &sig, &address), Ok(()));
 
-pub async fn send_eth_evm(to: String, amount: f64, dest_chain: String) -> Result<String, String> {
-    use crate::evm_indexer::{ChainService, CHAIN_SERVICE};
+        let flipped_parity = ethers_core::types::Signature {
+            v: 1 - sig.v,
+            ..sig
+        };
+        let err = verify_signer(&tx_hash, &flipped_parity, &address).unwrap_err();
+        assert!(err.ends_with(&format!("expected {}; not broadcasting", address)));
 
-    let chain_service = CHAIN_SERVICE.with(|service| {
-        let mut service = service.borrow_mut();
-        if service.is_none() {
-            let canister_id = ic_cdk::api::id().to_string();
-            *service = Some(ChainService::new(canister_id));
-        }
-        service.clone()
-    });
+        let tampered = ethers_core::types::Signature {
+            r: sig.r + 1,
+            ..sig
+        };
+        assert!(verify_signer(&tx_hash, &tampered, &address).is_err());
+    }
 
-    if let Some(service) = chain_service {
-        // Amount is passed as ETH decimal string (e.g. "0.00008109335274785089")
+    #[test]
+    fn inconsistent_nonce_is_refetched_until_consistent() {
+        use crate::evm_rpc_bindings::EthSepoliaService;
 
-        service
-            .send_eth_evm(to, amount.to_string(), dest_chain)
-            .await
-    } else {
-        Err("Failed to initialize chain service".to_string())
+        let args = GetTransactionCountArgs {
+            address: "0x52908400098527886E0F7030069857D2E4169EE7".to_string(),
+            block: BlockTag::Pending,
+        };
+        let inconsistent = || {
+            MultiGetTransactionCountResult::Inconsistent(vec![
+                (
+                    RpcService::EthSepolia(EthSepoliaService::Alchemy),
+                    GetTransactionCountResult::Ok(Nat::from(4u64)),
+                ),
+                (
+                    RpcService::EthSepolia(EthSepoliaService::Ankr),
+                    GetTransactionCountResult::Ok(Nat::from(5u64)),
+                ),
+            ])
+        };
+        let mut responses = vec![
+            MultiGetTransactionCountResult::Consistent(GetTransactionCountResult::Ok(Nat::from(
+                6u64,
+            ))),
+            inconsistent(),
+        ];
+        let request = || {
+            let response = responses.pop().unwrap();
+            async move { Ok(response) }
+        };
+        assert_eq!(
+            poll_once(settle_transaction_count(&args, 2, Duration::ZERO, request)),
+            Poll::Ready(Ok(Nat::from(6u64)))
+        );
+        assert!(responses.is_empty());
+
+        // Out of retries, the highest reported count is used
+        let mut responses = vec![inconsistent()];
+        let request = || {
+            let response = responses.pop().unwrap();
+            async move { Ok(response) }
+        };
+        assert_eq!(
+            poll_once(settle_transaction_count(&args, 0, Duration::ZERO, request)),
+            Poll::Ready(Ok(Nat::from(5u64)))
+        );
     }
-}
 
-// Wrapper function to call ChainService transfer_eth method with caller derivation path
-#[ic_cdk::update]
-pub async fn transfer_eth(to: String, amount: f64, dest_chain: String) -> Result<String, String> {
-    use crate::evm_indexer::{ChainService, CHAIN_SERVICE};
+    #[test]
+    fn failing_chain_reports_its_error_alongside_other_balances() {
+        let fetch_balance = |dest_chain: String| async move {
+            match dest_chain.as_str() {
+                "17000" => Ok("81000000000000".to_string()),
+                _ => Err("RPC unavailable".to_string()),
+            }
+        };
+        let chains = vec!["17000".to_string(), "8453".to_string()];
+        assert_eq!(
+            poll_once(balances_by_chain(chains, fetch_balance)),
+            Poll::Ready(vec![
+                ("17000".to_string(), "81000000000000".to_string()),
+                ("8453".to_string(), "error: RPC unavailable".to_string()),
+            ])
+        );
+    }
 
-    let chain_service = CHAIN_SERVICE.with(|service| {
-        let mut service = service.borrow_mut();
-        if service.is_none() {
-            let canister_id = ic_cdk::api::id().to_string();
-            *service = Some(ChainService::new(canister_id));
-        }
-        service.clone()
-    });
+    #[test]
+    fn l2_gas_limit_grace_raises_the_final_gas_limit() {
+        let l1 = get_chain_config("17000");
+        let l2 = get_chain_config("8453");
+        assert_eq!((l1.gas_limit_grace(), l2.gas_limit_grace()), (1.0, 1.2));
 
-    if let Some(service) = chain_service {
-        // Amount is passed as ETH decimal string (e.g. "0.00008109335274785089")
-        service
-            .transfer_eth(to, amount.to_string(), dest_chain)
-            .await
-    } else {
-        Err("Failed to initialize chain service".to_string())
+        let l1_limit = apply_gas_limit_grace(21_000, l1.gas_limit_grace());
+        let l2_limit = apply_gas_limit_grace(21_000, l2.gas_limit_grace());
+        assert_eq!(l1_limit, 21_000);
+        assert_eq!(l2_limit, 25_200);
+        assert!(l2_limit > l1_limit);
+
+        let configured = ChainConfig {
+            gas_limit_grace: Some(1.5),
+            ..l1
+        };
+        assert_eq!(
+            apply_gas_limit_grace(ERC20_TRANSFER_GAS_LIMIT, configured.gas_limit_grace()),
+            (ERC20_TRANSFER_GAS_LIMIT as f64 * 1.5).ceil() as u128
+        );
+        // A grace below 1 never lowers the limit
+        assert_eq!(apply_gas_limit_grace(21_000, 0.5), 21_000);
     }
-}
 
-fn key_id() -> EcdsaKeyId {
-    EcdsaKeyId {
-        curve: EcdsaCurve::Secp256k1,
-        name: "test_key_1".to_string(), // use EcdsaKeyId::default() for mainnet use test_key_1 for testnet and test_key_1 for local deployment
+    #[test]
+    fn evm_key_pairs_reject_unknown_chains_before_signing() {
+        for result in [
+            poll_once(generate_key_pair_evm("mars".to_string())),
+            poll_once(generate_canister_key_pair_evm("84532".to_string())),
+        ] {
+            assert!(matches!(result, Poll::Ready(Err(e)) if e.contains("chain")));
+        }
     }
-}
 
-pub async fn holesky_txn() -> Result<String, String> {
-    let hash = TX_HASH.read().unwrap();
-    if let Some(ref txn) = *hash {
-        ic_cdk::println!("Returning latest Holesky tx hash: {}", txn);
-        Ok(txn.clone())
-    } else {
-        Err("No transaction hash stored.".to_string())
+    #[test]
+    fn vault_balances_for_an_unknown_chain_report_the_error() {
+        let Poll::Ready(rows) = poll_once(get_vault_balances("mars".to_string())) else {
+            panic!("an unknown chain should fail before any call");
+        };
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].0.as_str(), rows[0].1.as_str()), ("error", ""));
+        assert!(rows[0].2.starts_with("error: Unknown destination chain"));
+    }
+
+    #[test]
+    fn largest_contract_code_fits_the_response_cap() {
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": format!("0x{}", "60".repeat(24_576)),
+        })
+        .to_string();
+        assert!(response.len() as u64 <= CODE_RESPONSE_BYTES);
+        assert!(response.len() as u64 > DEFAULT_RESPONSE_BYTES);
     }
 }
@@ -61,6 +61,62 @@ thread_local! {
     pub static CHAIN_SERVICE: RefCell<Option<ChainService>> = RefCell::new(None);
 }
 
+// EVM RPC canister on mainnet, used unless another principal is configured
+const DEFAULT_EVM_RPC_PRINCIPAL: &str = "7hfb6-caaaa-aaaar-qadga-cai";
+
+thread_local! {
+    static EVM_RPC_PRINCIPAL: RefCell<Option<Principal>> = const { RefCell::new(None) };
+}
+
+/// The configured EVM RPC canister, or the mainnet default
+pub fn evm_rpc_principal() -> Principal {
+    EVM_RPC_PRINCIPAL
+        .with(|principal| *principal.borrow())
+        .unwrap_or_else(|| Principal::from_text(DEFAULT_EVM_RPC_PRINCIPAL).unwrap())
+}
+
+/// The explicitly configured EVM RPC canister, if any (persisted across upgrades)
+pub fn configured_evm_rpc_principal() -> Option<Principal> {
+    EVM_RPC_PRINCIPAL.with(|principal| *principal.borrow())
+}
+
+/// Point outbound EVM RPC calls at `principal` (`None` restores the default), including
+/// the already-initialized chain service
+pub fn configure_evm_rpc_principal(principal: Option<Principal>) {
+    EVM_RPC_PRINCIPAL.with(|current| *current.borrow_mut() = principal);
+    let evm_rpc = evm_rpc_principal();
+    CHAIN_SERVICE.with(|service| {
+        if let Some(service) = service.borrow_mut().as_mut() {
+            service.evm_rpc = EvmRpcService(evm_rpc);
+        }
+    });
+}
+
+/// The shared chain service, created on first use. Construction runs outside the
+/// `CHAIN_SERVICE` borrow, so a failure leaves the cell untouched
+pub fn chain_service() -> Result<ChainService, String> {
+    if let Some(service) = CHAIN_SERVICE.with(|service| service.borrow().clone()) {
+        return Ok(service);
+    }
+    let service = ChainService::new(ic_cdk::api::id().to_string())
+        .map_err(|e| format!("Failed to initialize chain service: {}", e))?;
+    CHAIN_SERVICE.with(|cell| *cell.borrow_mut() = Some(service.clone()));
+    Ok(service)
+}
+
+#[update]
+fn set_evm_rpc_principal(principal: Principal) -> Result<(), String> {
+    crate::require_controller()?;
+
+    configure_evm_rpc_principal(Some(principal));
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_evm_rpc_principal() -> Principal {
+    evm_rpc_principal()
+}
+
 #[derive(Clone, Debug)]
 pub struct ChainService {
     canister_id: String,
@@ -81,18 +137,19 @@ pub struct LogDetails {
 }
 
 impl ChainService {
-    pub fn new(canister_id: String) -> Self {
-        let principal = Principal::from_text("7hfb6-caaaa-aaaar-qadga-cai").unwrap();
-        let evm_rpc = EvmRpcService(principal);
+    pub fn new(canister_id: String) -> Result<Self, String> {
+        Principal::from_text(&canister_id)
+            .map_err(|e| format!("Invalid canister id '{}': {}", canister_id, e))?;
+        let evm_rpc = EvmRpcService(evm_rpc_principal());
         let last_checked_time = RefCell::new(time() / 1_000_000);
         let timer_id = RefCell::new(None);
 
-        ChainService {
+        Ok(ChainService {
             canister_id,
             evm_rpc,
             last_checked_time,
             timer_id,
-        }
+        })
     }
 
 
@@ -565,19 +622,42 @@ impl ChainService {
 
 #[update]
 async fn start_periodic_fetch(block_number: u64) {
-    let service = CHAIN_SERVICE.with(|service_cell| {
-        let mut service = service_cell.borrow_mut();
-        if service.is_none() {
-            let canister_id = ic_cdk::api::id().to_string();
-            *service = Some(ChainService::new(canister_id));
-        }
-        service.clone()
-    });
-    
-    if let Some(service) = service {
-        service.start_periodic_fetch(block_number).await;
+    match chain_service() {
+        Ok(service) => service.start_periodic_fetch(block_number).await,
+        Err(e) => ic_cdk::println!("❌ {}", e),
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_evm_rpc_principal_is_the_call_target() {
+        let default = Principal::from_text(DEFAULT_EVM_RPC_PRINCIPAL).unwrap();
+        assert_eq!(get_evm_rpc_principal(), default);
+
+        CHAIN_SERVICE.with(|service| {
+            *service.borrow_mut() = Some(ChainService {
+                canister_id: "aaaaa-aa".to_string(),
+                evm_rpc: EvmRpcService(default),
+                last_checked_time: RefCell::new(0),
+                timer_id: RefCell::new(None),
+            })
+        });
+        let custom = Principal::from_slice(&[9; 10]);
+        configure_evm_rpc_principal(Some(custom));
+        assert_eq!(get_evm_rpc_principal(), custom);
+        assert_eq!(chain_service().unwrap().evm_rpc.0, custom);
+
+        configure_evm_rpc_principal(None);
+        assert_eq!(chain_service().unwrap().evm_rpc.0, default);
+    }
 
-
+    #[test]
+    fn failed_chain_service_init_reports_a_clear_error() {
+        let error = ChainService::new("not a principal".to_string()).unwrap_err();
+        assert!(error.starts_with("Invalid canister id 'not a principal'"));
+        assert!(CHAIN_SERVICE.with(|service| service.borrow().is_none()));
+    }
+}
@@ -1,10 +1,23 @@
 use base32::Alphabet;
-use candid::{CandidType, Principal};
+use candid::{CandidType, Nat, Principal};
 use crc16::{State, XMODEM};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryInto;
 
-use crate::{evm_indexer::CHAIN_SERVICE, stellar_indexer::CandidContractEvent};
+use crate::{
+    eth::{
+        AddressCheck, CallerUsage, ChainConfig, DecodedTx, GasOverrides, RecipientAllowlist,
+        SupportedChain, TokenConfig, TxFinality,
+    },
+    evm_indexer::chain_service,
+    stellar_indexer::{
+        AttentionItems, AutoScanConfig, BridgeOutcome, BridgeRecord, CandidContractEvent,
+        ConversionConfig, CursorScan, DecodedEvent, EventResult, EventsPage, PayoutQuote,
+        PriceConfig, RateInfo, RateLimitConfig, ScanConfig, ScanSummary, Subscriber, VolumeStat,
+        XlmEthRate,
+    },
+};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use candid::Func;
 use ic_cdk::api::management_canister::http_request::{
@@ -81,6 +94,195 @@ struct AccountResponse {
     sequence: String,
 }
 
+/// Rejects the call unless the caller is a controller of this canister
+pub(crate) fn require_controller() -> Result<(), String> {
+    check_controller(ic_cdk::api::is_controller(&ic_cdk::api::caller()))
+}
+
+// The guard behind `require_controller`, given whether the caller is a controller
+pub(crate) fn check_controller(is_controller: bool) -> Result<(), String> {
+    if is_controller {
+        Ok(())
+    } else {
+        Err("Caller is not a controller of this canister".to_string())
+    }
+}
+
+// State carried across upgrades. New fields must be `Option` so snapshots taken by
+// older versions still decode
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    rate_limit: Option<stellar_indexer::RateLimitState>,
+    notification_url: Option<Option<String>>,
+    evm_rpc_principal: Option<Principal>,
+    conversion: Option<stellar_indexer::ConversionConfig>,
+    reject_self_recipient: Option<bool>,
+    volume: Option<HashMap<(String, String), stellar_indexer::VolumeCounter>>,
+    scan_cursors: Option<HashMap<String, u32>>,
+    auto_scan: Option<stellar_indexer::AutoScanConfig>,
+    scan_config: Option<stellar_indexer::ScanConfig>,
+    simulate_before_send: Option<bool>,
+    source_token_decimals: Option<HashMap<String, u8>>,
+    sign_attempts: Option<u32>,
+    nonce_fetch_retries: Option<u32>,
+    subscribers: Option<Vec<stellar_indexer::Subscriber>>,
+    blocked_principals: Option<BTreeSet<Principal>>,
+    caller_usage: Option<BTreeMap<Principal, eth::CallerUsage>>,
+    ens_registries: Option<HashMap<String, String>>,
+    source_token_rates: Option<HashMap<String, stellar_indexer::XlmEthRate>>,
+    recipient_allowlist: Option<eth::RecipientAllowlist>,
+    payout_token_rates: Option<HashMap<(String, String), stellar_indexer::XlmEthRate>>,
+    bridge_records: Option<HashMap<String, stellar_indexer::BridgeRecord>>,
+    events: Option<Vec<stellar_indexer::CandidContractEvent>>,
+    chain_configs: Option<HashMap<u64, eth::ChainConfig>>,
+    token_configs: Option<HashMap<(String, String), eth::TokenConfig>>,
+    vault_labels: Option<Vec<String>>,
+    price_config: Option<stellar_indexer::PriceConfig>,
+    scan_counter: Option<u64>,
+    transform_audit: Option<stellar_indexer::TransformAuditState>,
+    sent_txs: Option<HashMap<String, eth::TxRecord>>,
+}
+
+// Optionally overrides the EVM RPC canister (e.g. a local replica's) at install time
+#[ic_cdk::init]
+fn init(evm_rpc_principal: Option<Principal>) {
+    evm_indexer::configure_evm_rpc_principal(evm_rpc_principal);
+    eth::schedule_warm_up();
+}
+
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        rate_limit: Some(stellar_indexer::rate_limit_snapshot()),
+        notification_url: Some(stellar_indexer::notification_url()),
+        evm_rpc_principal: evm_indexer::configured_evm_rpc_principal(),
+        conversion: Some(stellar_indexer::conversion_config()),
+        reject_self_recipient: Some(eth::reject_self_recipient()),
+        volume: Some(stellar_indexer::volume_snapshot()),
+        scan_cursors: Some(stellar_indexer::scan_cursors_snapshot()),
+        auto_scan: stellar_indexer::auto_scan_snapshot(),
+        scan_config: Some(stellar_indexer::scan_config()),
+        simulate_before_send: Some(eth::simulate_before_send()),
+        source_token_decimals: Some(stellar_indexer::source_token_decimals_snapshot()),
+        sign_attempts: Some(eth::sign_attempts()),
+        nonce_fetch_retries: Some(eth::nonce_fetch_retries()),
+        subscribers: Some(stellar_indexer::subscribers_snapshot()),
+        blocked_principals: Some(eth::blocked_principals_snapshot()),
+        caller_usage: Some(eth::caller_usage_snapshot()),
+        ens_registries: Some(eth::ens_registries()),
+        source_token_rates: Some(stellar_indexer::source_token_rates_snapshot()),
+        recipient_allowlist: Some(eth::recipient_allowlist()),
+        payout_token_rates: Some(stellar_indexer::payout_token_rates_snapshot()),
+        bridge_records: Some(stellar_indexer::bridge_records_snapshot()),
+        events: Some(stellar_indexer::events_snapshot()),
+        chain_configs: Some(eth::chain_configs_snapshot()),
+        token_configs: Some(eth::token_configs_snapshot()),
+        vault_labels: Some(eth::vault_labels_snapshot()),
+        price_config: Some(stellar_indexer::price_config()),
+        scan_counter: Some(stellar_indexer::scan_counter()),
+        transform_audit: Some(stellar_indexer::transform_audit_snapshot()),
+        sent_txs: Some(eth::sent_txs_snapshot()),
+    };
+    ic_cdk::storage::stable_save((state,)).expect("Failed to save stable state");
+}
+
+// An upgrade argument overrides the EVM RPC principal carried over in stable state
+#[ic_cdk::post_upgrade]
+fn post_upgrade(evm_rpc_principal: Option<Principal>) {
+    match ic_cdk::storage::stable_restore::<(StableState,)>() {
+        Ok((state,)) => {
+            if let Some(rate_limit) = state.rate_limit {
+                stellar_indexer::restore_rate_limit(rate_limit);
+            }
+            if let Some(url) = state.notification_url {
+                stellar_indexer::restore_notification_url(url);
+            }
+            evm_indexer::configure_evm_rpc_principal(state.evm_rpc_principal);
+            if let Some(conversion) = state.conversion {
+                stellar_indexer::restore_conversion_config(conversion);
+            }
+            if let Some(reject) = state.reject_self_recipient {
+                eth::restore_reject_self_recipient(reject);
+            }
+            if let Some(volume) = state.volume {
+                stellar_indexer::restore_volume(volume);
+            }
+            if let Some(cursors) = state.scan_cursors {
+                stellar_indexer::restore_scan_cursors(cursors);
+            }
+            stellar_indexer::restore_auto_scan(state.auto_scan);
+            if let Some(scan_config) = state.scan_config {
+                stellar_indexer::restore_scan_config(scan_config);
+            }
+            if let Some(simulate) = state.simulate_before_send {
+                eth::restore_simulate_before_send(simulate);
+            }
+            if let Some(decimals) = state.source_token_decimals {
+                stellar_indexer::restore_source_token_decimals(decimals);
+            }
+            if let Some(attempts) = state.sign_attempts {
+                eth::restore_sign_attempts(attempts);
+            }
+            if let Some(retries) = state.nonce_fetch_retries {
+                eth::restore_nonce_fetch_retries(retries);
+            }
+            if let Some(subscribers) = state.subscribers {
+                stellar_indexer::restore_subscribers(subscribers);
+            }
+            if let Some(blocked) = state.blocked_principals {
+                eth::restore_blocked_principals(blocked);
+            }
+            if let Some(usage) = state.caller_usage {
+                eth::restore_caller_usage(usage);
+            }
+            if let Some(registries) = state.ens_registries {
+                eth::restore_ens_registries(registries);
+            }
+            if let Some(rates) = state.source_token_rates {
+                stellar_indexer::restore_source_token_rates(rates);
+            }
+            if let Some(allowlist) = state.recipient_allowlist {
+                eth::restore_recipient_allowlist(allowlist);
+            }
+            if let Some(rates) = state.payout_token_rates {
+                stellar_indexer::restore_payout_token_rates(rates);
+            }
+            if let Some(records) = state.bridge_records {
+                stellar_indexer::restore_bridge_records(records);
+            }
+            if let Some(events) = state.events {
+                stellar_indexer::restore_events(events);
+            }
+            if let Some(configs) = state.chain_configs {
+                eth::restore_chain_configs(configs);
+            }
+            if let Some(configs) = state.token_configs {
+                eth::restore_token_configs(configs);
+            }
+            if let Some(labels) = state.vault_labels {
+                eth::restore_vault_labels(labels);
+            }
+            if let Some(config) = state.price_config {
+                stellar_indexer::restore_price_config(config);
+            }
+            if let Some(count) = state.scan_counter {
+                stellar_indexer::restore_scan_counter(count);
+            }
+            if let Some(audit) = state.transform_audit {
+                stellar_indexer::restore_transform_audit(audit);
+            }
+            if let Some(txs) = state.sent_txs {
+                eth::restore_sent_txs(txs);
+            }
+        }
+        Err(e) => ic_cdk::println!("No stable state restored: {}", e),
+    }
+    if evm_rpc_principal.is_some() {
+        evm_indexer::configure_evm_rpc_principal(evm_rpc_principal);
+    }
+    eth::schedule_warm_up();
+}
+
 // Transform function to make HTTP responses deterministic
 #[ic_cdk::query]
 fn transform_http_response(raw: TransformArgs) -> HttpResponse {
@@ -324,17 +526,9 @@ async fn get_sequence_number(public_key: &str, network: &str) -> Result<i64, Str
 
 #[ic_cdk::update]
 async fn evm_block_fetch(block_number: u64) {
-    let service = CHAIN_SERVICE.with(|service_cell| {
-        let mut service = service_cell.borrow_mut();
-        if service.is_none() {
-            let canister_id = ic_cdk::api::id().to_string();
-            *service = Some(crate::evm_indexer::ChainService::new(canister_id));
-        }
-        service.clone()
-    });
-
-    if let Some(service) = service {
-        service.start_periodic_fetch(block_number).await;
+    match chain_service() {
+        Ok(service) => service.start_periodic_fetch(block_number).await,
+        Err(e) => ic_cdk::println!("❌ {}", e),
     }
 }
 
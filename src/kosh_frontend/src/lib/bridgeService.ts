@@ -395,12 +395,13 @@ export const buildStellarTransaction = async (
             await new Promise(resolve => setTimeout(resolve, 8000));
             
             // Call fetch_stellar_events with ledger number and destination chain to start indexing and release
-            const release_tnx = await actor.fetch_stellar_events(ledgerNumber, params.destChain);
+            const release_tnx = await actor.fetch_stellar_events(ledgerNumber, params.destChain, [], []);
             console.log("🔍 Stellar events response:", release_tnx);
             
-            // Extract Holesky transaction hash from the response
+            // Extract Holesky transaction hash from the first paid event in the outcome
             if (release_tnx && 'Ok' in release_tnx) {
-              const holeskyTxHash = release_tnx.Ok.trim();
+              const paidEvent = release_tnx.Ok.events.find((event: any) => 'Ok' in event.result);
+              const holeskyTxHash = paidEvent ? paidEvent.result.Ok.trim() : '';
               
               if (holeskyTxHash && holeskyTxHash.startsWith('0x')) {
                 // Update progress when building release transaction
@@ -393,7 +393,7 @@ export const useAuth = () => {
       }
       
       // Generate new EVM address
-      const result = await actor.generate_key_pair_evm();
+      const result = await actor.generate_key_pair_evm('17000');
       console.log('🔗 EVM address generation result:', result);
       
       if (result.Ok) {